@@ -1,7 +1,15 @@
+use std::time::{Duration, Instant};
+
+use voice_control::choice::{AmbiguityPolicy, ChoiceConfig};
 use voice_control::desktop_control::Action;
+use voice_control::events::Events;
 use voice_control::load_voice_control;
 use voice_control::parser::{choose, number::digit, number::number, IntoParser, Parser};
 use voice_control::parser::{roundy, IsParser};
+use voice_control::recognition::{Recognition, Timing};
+use voice_control::recognizer::{Coqui, RecognizerConfig};
+use voice_control::regression::{run_regression, Case, CaseResult};
+use voice_control::streaming::StreamingRecognizer;
 
 fn parse_testing() -> Parser<Action> {
     "testing".map(|_| Action::new("Testing!".to_string(), || println!("I am running a test!")))
@@ -46,22 +54,143 @@ fn parse_mice() -> Parser<Action> {
 fn bench_recognize(audio: &str, name: &str, parser: impl Fn() -> Parser<Action>) {
     let data = voice_control::load_data(&format!("test-audio/{audio}.wav"));
 
-    let mut recognizer = load_voice_control(parser);
+    let config = RecognizerConfig::find().unwrap();
+    let mut recognizer = load_voice_control(
+        Coqui::load(&config).unwrap(),
+        &ChoiceConfig::default(),
+        parser,
+    )
+    .unwrap();
     println!(
         "   *** {name} *** {}",
         scaling::bench(|| { recognizer(&data) })
     );
 }
 
+/// Compare the time from the end of speech until we know what to do, when
+/// decoding the whole phrase at the end versus decoding while it is spoken.
+fn bench_latency(audio: &str, name: &str, parser: impl Fn() -> Parser<Action>) {
+    const CHUNK: usize = 1024;
+    let data = voice_control::load_data(&format!("test-audio/{audio}.wav"));
+
+    let config = RecognizerConfig::find().unwrap();
+    let mut recognizer = load_voice_control(
+        Coqui::load(&config).unwrap(),
+        &ChoiceConfig::default(),
+        &parser,
+    )
+    .unwrap();
+    let start = Instant::now();
+    recognizer(&data).unwrap();
+    let batch = start.elapsed();
+
+    let commands = parser();
+    let mut streaming = StreamingRecognizer::new(
+        move || Coqui::load(&config),
+        commands.to_checker(),
+        &Events::new(),
+    )
+    .unwrap();
+    // Feed the audio in as fast as it would be spoken.
+    for end in (CHUNK..data.len()).step_by(CHUNK) {
+        streaming.update(&data[..end]);
+        std::thread::sleep(Duration::from_secs_f64(CHUNK as f64 / 16_000.0));
+    }
+    let start = Instant::now();
+    let transcripts = streaming.finish(&data);
+    Recognition::new(
+        transcripts,
+        &commands,
+        &ChoiceConfig::default(),
+        Timing::default(),
+    );
+    let streamed = start.elapsed();
+    println!("   {name:>15} latency: batch {batch:.2?}, streaming {streamed:.2?}");
+}
+
+/// Clips where nothing was said, which shouldn't trigger any command.
+fn non_speech() -> Vec<(&'static str, Vec<i16>)> {
+    let mut x: u32 = 1;
+    let noise = (0..16_000)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            (x % 2001) as i16 - 1000
+        })
+        .collect();
+    vec![("silence", vec![0; 16_000]), ("noise", noise)]
+}
+
+/// How well we recognize the test clips, plus some silence and noise, and
+/// how long it takes.
+fn bench_accuracy(name: &str, choice: &ChoiceConfig, parser: impl Fn() -> Parser<Action>) {
+    let commands = parser();
+    let cases: Vec<Case> = [
+        ("testing", "testing"),
+        ("testing-testing-testing", "testing testing testing"),
+        (
+            "testing-testing-testing-unrecognized",
+            "testing testing testing",
+        ),
+        ("one-up", "one up"),
+    ]
+    .into_iter()
+    .map(|(audio, text)| Case {
+        wav: format!("test-audio/{audio}.wav").into(),
+        text: Some(text.to_string()),
+        action: None,
+        // Chatter, for grammars without this command.
+        not_a_command: !matches!(commands.parse(text), Ok((_, ""))),
+    })
+    .collect();
+
+    let config = RecognizerConfig::find().unwrap();
+    let mut recognizer =
+        load_voice_control(Coqui::load(&config).unwrap(), choice, &parser).unwrap();
+    let mut recognizing = Duration::ZERO;
+    let mut clips = 0;
+    let mut recognize = |data: &[i16]| {
+        let recognition = recognizer(data)?;
+        recognizing += recognition.timing.recognizing;
+        clips += 1;
+        Ok(recognition)
+    };
+    let mut report = run_regression(&cases, &commands, &mut recognize).unwrap();
+    for (audio, data) in non_speech() {
+        let case = Case {
+            wav: audio.into(),
+            text: None,
+            action: None,
+            not_a_command: false,
+        };
+        let recognition = recognize(&data).unwrap();
+        report
+            .results
+            .push(CaseResult::new(&case, &recognition, &commands));
+    }
+    println!(
+        "   {name:>15} {:?}: {}, {:.2?} per clip",
+        choice.ambiguity,
+        report.metrics(),
+        recognizing / clips
+    );
+}
+
 fn bench_parse(text: &str, name: &str, parser: impl Fn() -> Parser<Action>) {
     let parser = parser();
     let checker = parser.to_checker();
+    let compiled = parser.compile();
     println!(
-        "   {name:>15}      : {}",
+        "   {name:>15}         : {}",
         scaling::bench(|| { parser.parse(text) })
     );
     println!(
-        "   {name:>15} check: {}",
+        "   {name:>15} compiled: {}",
+        scaling::bench(|| { compiled.parse(text) })
+    );
+    println!(
+        "   {name:>15}    check: {}",
         scaling::bench(|| { checker(text) })
     );
 }
@@ -75,6 +204,11 @@ fn main() {
         "testing",
         "four",
         "bogus",
+        "spell alpha bravo",
+        "twenty one blind mice",
+        "one up",
+        "five left",
+        "two up three left left",
     ] {
         println!("{text}:");
         bench_parse(text, "testing", parse_testing);
@@ -96,4 +230,21 @@ fn main() {
         bench_recognize(audio, "mice_testing", parse_mice_testing);
         bench_recognize(audio, "roundy", roundy::parser);
     }
+
+    for audio in ["testing-testing-testing", "testing"] {
+        println!("{audio}:");
+        bench_latency(audio, "testing_mice", parse_testing_mice);
+        bench_latency(audio, "roundy", roundy::parser);
+    }
+
+    println!("accuracy:");
+    for ambiguity in [AmbiguityPolicy::TakeBest, AmbiguityPolicy::Reject] {
+        let choice = ChoiceConfig {
+            ambiguity,
+            ..ChoiceConfig::default()
+        };
+        bench_accuracy("testing_mice", &choice, parse_testing_mice);
+        bench_accuracy("mice_testing", &choice, parse_mice_testing);
+        bench_accuracy("roundy", &choice, roundy::parser);
+    }
 }
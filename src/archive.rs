@@ -0,0 +1,406 @@
+//! Keeping the utterances we hear, so that we can find out what went wrong
+//! and one day train on them.
+//!
+//! Each clip is a WAV file with a JSON sidecar next to it describing what we
+//! made of it, e.g. `000042-testing-testing.wav` and
+//! `000042-testing-testing.json`.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::recognition::{Hypothesis, Recognition};
+
+/// Where to keep clips, and for how long.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchiveConfig {
+    pub directory: PathBuf,
+    /// Delete the oldest clips once the archive is bigger than this many
+    /// bytes.
+    pub max_bytes: Option<u64>,
+    /// Delete clips older than this.
+    pub max_age: Option<Duration>,
+}
+
+impl ArchiveConfig {
+    /// Keep every clip in `directory` forever.
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        ArchiveConfig {
+            directory: directory.into(),
+            max_bytes: None,
+            max_age: None,
+        }
+    }
+}
+
+/// What we know about a clip, as stored in its sidecar.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClipMetadata {
+    /// When the utterance was recorded, in seconds since the Unix epoch.
+    pub recorded_at: f64,
+    /// When the utterance started and ended, in seconds from the start of
+    /// the audio.
+    pub start: f64,
+    pub end: f64,
+    /// Every guess the recognizer made, most confident first.
+    pub hypotheses: Vec<Hypothesis>,
+    /// The index of the hypothesis we went with.
+    pub chosen: Option<usize>,
+    pub ambiguous: bool,
+    /// What we did about it, if anything.
+    pub action: Option<String>,
+    /// What was really said, according to a person who listened to it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correction: Option<String>,
+    /// The correction isn't a command, and they said that was fine.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub not_a_command: bool,
+}
+
+impl ClipMetadata {
+    /// Describe an utterance from `start` to `end`, which we recognized as
+    /// `recognition` and which led us to do `action`.
+    pub fn new<T>(
+        start: Duration,
+        end: Duration,
+        recognition: &Recognition<T>,
+        action: Option<String>,
+    ) -> Self {
+        let recorded_at = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        ClipMetadata {
+            recorded_at: recorded_at.as_secs_f64(),
+            start: start.as_secs_f64(),
+            end: end.as_secs_f64(),
+            hypotheses: recognition.hypotheses.clone(),
+            chosen: recognition.chosen,
+            ambiguous: recognition.ambiguous,
+            action,
+            correction: None,
+            not_a_command: false,
+        }
+    }
+
+    /// The text of the hypothesis we went with.
+    pub fn chosen_text(&self) -> Option<&str> {
+        Some(&self.hypotheses.get(self.chosen?)?.text)
+    }
+}
+
+/// A clip in the archive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clip {
+    pub number: u64,
+    pub wav: PathBuf,
+    pub sidecar: PathBuf,
+}
+
+impl Clip {
+    pub fn metadata(&self) -> anyhow::Result<ClipMetadata> {
+        let json = std::fs::read_to_string(&self.sidecar)
+            .with_context(|| format!("reading {:?}", self.sidecar))?;
+        serde_json::from_str(&json).with_context(|| format!("parsing {:?}", self.sidecar))
+    }
+
+    /// Replace the clip's sidecar, e.g. to record a correction.
+    pub fn save_metadata(&self, metadata: &ClipMetadata) -> anyhow::Result<()> {
+        let json = serde_json::to_string_pretty(metadata)?;
+        std::fs::write(&self.sidecar, json).with_context(|| format!("saving {:?}", self.sidecar))
+    }
+
+    pub fn samples(&self) -> anyhow::Result<Vec<i16>> {
+        crate::audio::read_wav(&self.wav)
+    }
+
+    /// How much space the clip takes up.
+    fn size(&self) -> u64 {
+        [&self.wav, &self.sidecar]
+            .iter()
+            .filter_map(|p| p.metadata().ok())
+            .map(|m| m.len())
+            .sum()
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        self.wav.metadata().ok()?.modified().ok()
+    }
+
+    fn delete(&self) -> anyhow::Result<()> {
+        for path in [&self.wav, &self.sidecar] {
+            match std::fs::remove_file(path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(e).with_context(|| format!("deleting {path:?}"))
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A directory of clips.
+pub struct Archive {
+    config: ArchiveConfig,
+    /// The number of the next clip, which carries on from the clips already
+    /// there, so we never overwrite them.
+    next: u64,
+}
+
+impl Archive {
+    /// Open the archive, creating its directory if need be.
+    pub fn open(config: &ArchiveConfig) -> anyhow::Result<Self> {
+        let directory = &config.directory;
+        std::fs::create_dir_all(directory)
+            .with_context(|| format!("creating the archive {directory:?}"))?;
+        let mut archive = Archive {
+            config: config.clone(),
+            next: 0,
+        };
+        archive.next = archive.clips()?.last().map_or(0, |c| c.number + 1);
+        Ok(archive)
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.config.directory
+    }
+
+    /// Every clip in the archive, oldest first.
+    pub fn clips(&self) -> anyhow::Result<Vec<Clip>> {
+        list_clips(self.directory())
+    }
+
+    /// Save `samples` along with `metadata`, naming the clip after `name`,
+    /// and then make room if the archive has grown too big.  Returns the
+    /// new clip, and any that were deleted to make room.  The new clip is
+    /// never deleted, even if it is bigger than the archive can hold.
+    pub fn save(
+        &mut self,
+        samples: &[i16],
+        name: &str,
+        metadata: &ClipMetadata,
+    ) -> anyhow::Result<(Clip, Vec<Clip>)> {
+        let stem = format!("{:06}-{}", self.next, safe_name(name));
+        let clip = Clip {
+            number: self.next,
+            wav: self.directory().join(format!("{stem}.wav")),
+            sidecar: self.directory().join(format!("{stem}.json")),
+        };
+        self.next += 1;
+        crate::save_data(&clip.wav, samples).with_context(|| format!("saving {:?}", clip.wav))?;
+        clip.save_metadata(metadata)?;
+        let pruned = self.prune_except(SystemTime::now(), Some(&clip))?;
+        Ok((clip, pruned))
+    }
+
+    /// Delete clips that are too old, and then the oldest clips until the
+    /// archive is small enough, returning the clips we deleted.
+    pub fn prune(&self, now: SystemTime) -> anyhow::Result<Vec<Clip>> {
+        self.prune_except(now, None)
+    }
+
+    /// [`Archive::prune`], but leaving `keep` alone, though it still counts
+    /// towards the size of the archive.
+    fn prune_except(&self, now: SystemTime, keep: Option<&Clip>) -> anyhow::Result<Vec<Clip>> {
+        let mut clips = self.clips()?;
+        let kept_size = keep.map_or(0, Clip::size);
+        clips.retain(|clip| Some(clip) != keep);
+        let mut pruned = Vec::new();
+        if let Some(max_age) = self.config.max_age {
+            let (old, young) = clips.into_iter().partition(|clip| {
+                clip.modified()
+                    .and_then(|m| now.duration_since(m).ok())
+                    .is_some_and(|age| age > max_age)
+            });
+            pruned = old;
+            clips = young;
+        }
+        if let Some(max_bytes) = self.config.max_bytes {
+            let mut total: u64 = kept_size + clips.iter().map(Clip::size).sum::<u64>();
+            let mut clips = clips.into_iter();
+            while total > max_bytes {
+                match clips.next() {
+                    Some(clip) => {
+                        total -= clip.size();
+                        pruned.push(clip);
+                    }
+                    None => break,
+                }
+            }
+        }
+        for clip in &pruned {
+            clip.delete()?;
+        }
+        Ok(pruned)
+    }
+}
+
+/// The clips in `directory`, oldest first.
+pub fn list_clips(directory: &Path) -> anyhow::Result<Vec<Clip>> {
+    let mut clips = Vec::new();
+    let entries = std::fs::read_dir(directory).with_context(|| format!("reading {directory:?}"))?;
+    for entry in entries {
+        let wav = entry?.path();
+        if wav.extension() != Some("wav".as_ref()) {
+            continue;
+        }
+        let number = wav
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .and_then(|s| s.split('-').next())
+            .and_then(|n| n.parse().ok());
+        if let Some(number) = number {
+            let sidecar = wav.with_extension("json");
+            clips.push(Clip {
+                number,
+                wav,
+                sidecar,
+            });
+        }
+    }
+    clips.sort_by_key(|c| c.number);
+    Ok(clips)
+}
+
+/// Turn what we heard into something that is safe to use in a file name on
+/// any system.
+fn safe_name(name: &str) -> String {
+    let mut safe = String::new();
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            safe.push(c.to_ascii_lowercase());
+        } else if !safe.is_empty() && !safe.ends_with('-') {
+            safe.push('-');
+        }
+    }
+    safe.truncate(40);
+    let safe = safe.trim_end_matches('-');
+    if safe.is_empty() {
+        "unnamed".to_string()
+    } else {
+        safe.to_string()
+    }
+}
+
+#[test]
+fn safe_names() {
+    assert_eq!(safe_name("testing testing"), "testing-testing");
+    assert_eq!(safe_name("\"↑\" 🐭 Left/Right"), "left-right");
+    assert_eq!(safe_name("../../etc/passwd"), "etc-passwd");
+    assert_eq!(safe_name("↑"), "unnamed");
+    assert_eq!(safe_name(&"word ".repeat(20)).len(), 39);
+}
+
+/// What we might have made of a clip where we heard `texts`, and went with
+/// the `chosen` one.
+#[cfg(test)]
+pub(crate) fn metadata(
+    texts: &[&str],
+    chosen: Option<usize>,
+    correction: Option<&str>,
+) -> ClipMetadata {
+    use crate::recognition::Outcome;
+
+    ClipMetadata {
+        recorded_at: 1_600_000_000.0,
+        start: 1.0,
+        end: 2.5,
+        hypotheses: texts
+            .iter()
+            .enumerate()
+            .map(|(i, text)| Hypothesis {
+                text: text.to_string(),
+                confidence: -1.5,
+                outcome: if Some(i) == chosen {
+                    Outcome::Complete
+                } else {
+                    Outcome::Wrong
+                },
+            })
+            .collect(),
+        chosen,
+        ambiguous: false,
+        action: chosen.map(|i| format!("{:?}", texts[i])),
+        correction: correction.map(String::from),
+        not_a_command: false,
+    }
+}
+
+#[test]
+fn save_and_reopen() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = ArchiveConfig::new(dir.path().join("clips"));
+    let mut archive = Archive::open(&config).unwrap();
+    let (first, pruned) = archive
+        .save(
+            &[1, 2, 3],
+            "testing",
+            &metadata(&["testing"], Some(0), None),
+        )
+        .unwrap();
+    assert!(pruned.is_empty());
+    assert_eq!(first.wav, config.directory.join("000000-testing.wav"));
+    assert_eq!(crate::load_data(first.wav.to_str().unwrap()), [1, 2, 3]);
+    assert_eq!(
+        first.metadata().unwrap(),
+        metadata(&["testing"], Some(0), None)
+    );
+    assert_eq!(first.metadata().unwrap().chosen_text(), Some("testing"));
+
+    // Opening it again carries on where we left off.
+    let mut archive = Archive::open(&config).unwrap();
+    let (second, _) = archive
+        .save(&[4, 5], "one up", &metadata(&["one up"], Some(0), None))
+        .unwrap();
+    assert_eq!(second.wav, config.directory.join("000001-one-up.wav"));
+    assert_eq!(archive.clips().unwrap(), [first, second]);
+}
+
+#[test]
+fn retention() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut config = ArchiveConfig::new(dir.path());
+    let mut archive = Archive::open(&config).unwrap();
+    let mut clips = Vec::new();
+    for _ in 0..4 {
+        let (clip, _) = archive
+            .save(&[7; 1000], "seven", &metadata(&["seven"], Some(0), None))
+            .unwrap();
+        clips.push(clip);
+    }
+    let size = clips[0].size();
+
+    // Room for two and a bit clips.
+    config.max_bytes = Some(size * 5 / 2);
+    let archive = Archive::open(&config).unwrap();
+    assert_eq!(archive.prune(SystemTime::now()).unwrap(), clips[..2]);
+    assert_eq!(archive.clips().unwrap(), clips[2..]);
+
+    config.max_bytes = None;
+    config.max_age = Some(Duration::from_secs(60));
+    let archive = Archive::open(&config).unwrap();
+    assert!(archive.prune(SystemTime::now()).unwrap().is_empty());
+    let later = SystemTime::now() + Duration::from_secs(120);
+    assert_eq!(archive.prune(later).unwrap(), clips[2..]);
+    assert!(archive.clips().unwrap().is_empty());
+}
+
+#[test]
+fn saving_a_clip_too_big_to_keep() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut config = ArchiveConfig::new(dir.path());
+    let mut archive = Archive::open(&config).unwrap();
+    let (old, _) = archive
+        .save(&[7; 10], "seven", &metadata(&["seven"], Some(0), None))
+        .unwrap();
+
+    config.max_bytes = Some(old.size());
+    let mut archive = Archive::open(&config).unwrap();
+    let (new, pruned) = archive
+        .save(&[8; 1000], "eight", &metadata(&["eight"], Some(0), None))
+        .unwrap();
+    assert_eq!(pruned, [old]);
+    assert_eq!(archive.clips().unwrap(), [new]);
+}
@@ -0,0 +1,307 @@
+//! Sources of 16 kHz mono audio for [`voice_control_with_source`](crate::voice_control_with_source).
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+use crate::events::{Event, Events};
+
+mod microphone;
+pub mod resample;
+pub use microphone::{list_input_devices, DeviceInfo, DeviceSelector, Microphone};
+
+/// Something that produces 16 kHz mono audio, one chunk at a time.
+pub trait AudioSource {
+    /// Fetch the next chunk of samples.
+    ///
+    /// Returns `Ok(None)` once the source is exhausted.  A live microphone
+    /// never is, so this blocks until more audio arrives.  Problems that
+    /// don't stop the audio are reported to `events`.
+    fn next_chunk(&mut self, events: &Events) -> anyhow::Result<Option<Vec<i16>>>;
+
+    /// Try to get going again after [`next_chunk`](AudioSource::next_chunk)
+    /// has failed, e.g. because a USB microphone was unplugged.  An error
+    /// means we should give up.  Progress is reported to `events`.
+    fn reconnect(&mut self, _events: &Events) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!("this audio source cannot reconnect"))
+    }
+}
+
+impl<S: AudioSource + ?Sized> AudioSource for Box<S> {
+    fn next_chunk(&mut self, events: &Events) -> anyhow::Result<Option<Vec<i16>>> {
+        (**self).next_chunk(events)
+    }
+    fn reconnect(&mut self, events: &Events) -> anyhow::Result<()> {
+        (**self).reconnect(events)
+    }
+}
+
+/// What [`for_each_chunk`] hands on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chunk<'a> {
+    /// The next few samples.
+    Audio(&'a [i16]),
+    /// The source failed and we got it back, so whatever comes next doesn't
+    /// follow on from what came before.
+    Reconnected,
+}
+
+/// Hand every chunk from `source` to `f` until it runs out, reconnecting
+/// whenever it fails, and telling `events` and `f` about it.
+///
+/// Errors that we cannot recover from are returned, along with the failure
+/// that started it all.
+pub fn for_each_chunk(
+    source: &mut impl AudioSource,
+    events: &Events,
+    mut f: impl FnMut(Chunk),
+) -> anyhow::Result<()> {
+    loop {
+        match source.next_chunk(events) {
+            Ok(Some(chunk)) => f(Chunk::Audio(&chunk)),
+            Ok(None) => return Ok(()),
+            Err(e) => {
+                events.emit(Event::AudioLost {
+                    error: format!("{e:#}"),
+                });
+                source
+                    .reconnect(events)
+                    .with_context(|| format!("unable to recover from {e:#}"))?;
+                events.emit(Event::AudioBack);
+                f(Chunk::Reconnected);
+            }
+        }
+    }
+}
+
+/// How many samples we hand out at a time when replaying recorded audio.
+const CHUNK_SAMPLES: usize = 1024;
+
+/// Replay a sequence of WAV files as if they had been spoken into a
+/// microphone, with a second of silence after each one so that every file is
+/// treated as its own phrase.
+pub struct WavFiles {
+    files: std::vec::IntoIter<PathBuf>,
+    samples: Vec<i16>,
+    position: usize,
+}
+
+impl WavFiles {
+    pub fn new(files: impl IntoIterator<Item = PathBuf>) -> Self {
+        WavFiles {
+            files: files.into_iter().collect::<Vec<_>>().into_iter(),
+            samples: Vec::new(),
+            position: 0,
+        }
+    }
+
+    /// Replay a single WAV file, or every `.wav` file in a directory in
+    /// alphabetical order.
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        if path.is_dir() {
+            let mut files = Vec::new();
+            for entry in std::fs::read_dir(path).with_context(|| format!("reading {path:?}"))? {
+                let p = entry?.path();
+                if p.extension().map(|e| e == "wav").unwrap_or(false) {
+                    files.push(p);
+                }
+            }
+            files.sort();
+            Ok(WavFiles::new(files))
+        } else {
+            Ok(WavFiles::new([path.to_path_buf()]))
+        }
+    }
+}
+
+impl AudioSource for WavFiles {
+    fn next_chunk(&mut self, _: &Events) -> anyhow::Result<Option<Vec<i16>>> {
+        while self.position >= self.samples.len() {
+            if let Some(path) = self.files.next() {
+                self.samples = read_wav(&path)?;
+                self.samples
+                    .resize(self.samples.len() + crate::RATE_AS_USIZE, 0);
+                self.position = 0;
+            } else {
+                return Ok(None);
+            }
+        }
+        let end = std::cmp::min(self.position + CHUNK_SAMPLES, self.samples.len());
+        let chunk = self.samples[self.position..end].to_vec();
+        self.position = end;
+        Ok(Some(chunk))
+    }
+}
+
+pub(crate) fn read_wav(path: &Path) -> anyhow::Result<Vec<i16>> {
+    let reader = hound::WavReader::open(path).with_context(|| format!("opening {path:?}"))?;
+    let spec = reader.spec();
+    if spec.channels != 1
+        || spec.sample_rate != crate::REQUIRED_RATE.0
+        || spec.sample_format != hound::SampleFormat::Int
+        || spec.bits_per_sample != 16
+    {
+        anyhow::bail!("{path:?} is not 16 kHz mono 16-bit audio: {spec:?}");
+    }
+    reader
+        .into_samples()
+        .collect::<Result<Vec<i16>, _>>()
+        .with_context(|| format!("reading {path:?}"))
+}
+
+/// Raw 16 kHz mono signed 16-bit little-endian PCM, such as is produced by
+/// `arecord -f S16_LE -r 16000 -c 1 -t raw`.
+pub struct RawPcm<R> {
+    reader: R,
+    buffer: Vec<u8>,
+}
+
+impl<R: Read> RawPcm<R> {
+    pub fn new(reader: R) -> Self {
+        RawPcm {
+            reader,
+            buffer: vec![0; 2 * CHUNK_SAMPLES],
+        }
+    }
+}
+
+impl RawPcm<std::io::Stdin> {
+    pub fn stdin() -> Self {
+        RawPcm::new(std::io::stdin())
+    }
+}
+
+impl<R: Read> AudioSource for RawPcm<R> {
+    fn next_chunk(&mut self, _: &Events) -> anyhow::Result<Option<Vec<i16>>> {
+        let mut filled = 0;
+        while filled < self.buffer.len() {
+            match self.reader.read(&mut self.buffer[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e).context("reading raw audio"),
+            }
+        }
+        if filled < 2 {
+            return Ok(None);
+        }
+        Ok(Some(
+            self.buffer[..filled - filled % 2]
+                .chunks_exact(2)
+                .map(|b| i16::from_le_bytes([b[0], b[1]]))
+                .collect(),
+        ))
+    }
+}
+
+#[test]
+fn wav_files_are_followed_by_silence() {
+    let mut source = WavFiles::open("test-audio/testing.wav").unwrap();
+    let expected = crate::load_data("test-audio/testing.wav");
+    let mut samples = Vec::new();
+    while let Some(chunk) = source.next_chunk(&Events::new()).unwrap() {
+        assert!(chunk.len() <= CHUNK_SAMPLES);
+        samples.extend(chunk);
+    }
+    assert_eq!(samples.len(), expected.len() + crate::RATE_AS_USIZE);
+    assert_eq!(&samples[..expected.len()], &expected[..]);
+    assert!(samples[expected.len()..].iter().all(|&s| s == 0));
+}
+
+#[test]
+fn wav_directory_is_sorted() {
+    let dir = tempfile::tempdir().unwrap();
+    let first = dir.path().join("a.wav");
+    let second = dir.path().join("b.wav");
+    crate::save_data(second.to_str().unwrap(), &[2; 10]).unwrap();
+    crate::save_data(first.to_str().unwrap(), &[1; 10]).unwrap();
+    std::fs::write(dir.path().join("notes.txt"), "not audio").unwrap();
+
+    let mut source = WavFiles::open(dir.path()).unwrap();
+    let mut samples = Vec::new();
+    while let Some(chunk) = source.next_chunk(&Events::new()).unwrap() {
+        samples.extend(chunk);
+    }
+    assert_eq!(samples.len(), 2 * (10 + crate::RATE_AS_USIZE));
+    assert_eq!(&samples[..10], &[1; 10]);
+    assert_eq!(&samples[10 + crate::RATE_AS_USIZE..][..10], &[2; 10]);
+}
+
+#[test]
+fn raw_pcm() {
+    let data: Vec<i16> = (-1500..1500).collect();
+    let mut bytes: Vec<u8> = data.iter().flat_map(|s| s.to_le_bytes()).collect();
+    // A trailing odd byte is not a sample.
+    bytes.push(7);
+    let mut source = RawPcm::new(std::io::Cursor::new(bytes));
+    let mut samples = Vec::new();
+    while let Some(chunk) = source.next_chunk(&Events::new()).unwrap() {
+        samples.extend(chunk);
+    }
+    assert_eq!(data, samples);
+}
+
+#[test]
+fn reconnect_after_errors() {
+    struct Flaky {
+        chunks: Vec<anyhow::Result<Option<Vec<i16>>>>,
+        reconnects: usize,
+    }
+    impl AudioSource for Flaky {
+        fn next_chunk(&mut self, _: &Events) -> anyhow::Result<Option<Vec<i16>>> {
+            self.chunks.remove(0)
+        }
+        fn reconnect(&mut self, _: &Events) -> anyhow::Result<()> {
+            self.reconnects += 1;
+            Ok(())
+        }
+    }
+    let mut source = Flaky {
+        chunks: vec![
+            Ok(Some(vec![1])),
+            Err(anyhow::anyhow!("unplugged")),
+            Ok(Some(vec![2, 3])),
+            Ok(None),
+        ],
+        reconnects: 0,
+    };
+    let events = Events::new();
+    let lost = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let l = lost.clone();
+    events.subscribe(move |e: &Event| l.lock().unwrap().push(e.clone()));
+    let mut seen = Vec::new();
+    for_each_chunk(&mut source, &events, |chunk| {
+        seen.push(format!("{chunk:?}"))
+    })
+    .unwrap();
+    assert_eq!(seen, ["Audio([1])", "Reconnected", "Audio([2, 3])"]);
+    assert_eq!(source.reconnects, 1);
+    assert_eq!(
+        *lost.lock().unwrap(),
+        [
+            Event::AudioLost {
+                error: "unplugged".to_string()
+            },
+            Event::AudioBack
+        ]
+    );
+
+    // A source that cannot reconnect gives up with the original error.
+    let mut source = RawPcm::new(FailingReader);
+    let e = for_each_chunk(&mut source, &events, |_| ()).unwrap_err();
+    assert!(format!("{e:#}").contains("broken pipe"), "{e:#}");
+}
+
+#[cfg(test)]
+struct FailingReader;
+#[cfg(test)]
+impl Read for FailingReader {
+    fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::BrokenPipe,
+            "broken pipe",
+        ))
+    }
+}
@@ -0,0 +1,325 @@
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::time::Duration;
+
+use anyhow::Context;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+use super::resample::ToMono16kHz;
+use super::AudioSource;
+use crate::events::Events;
+use crate::REQUIRED_RATE;
+
+/// Which input device to listen to.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum DeviceSelector {
+    /// The system default input, or failing that the first usable device.
+    #[default]
+    Default,
+    /// The first device whose name contains this, ignoring case.
+    Name(String),
+    /// The device at this position in [`list_input_devices`].
+    Index(usize),
+}
+
+impl std::str::FromStr for DeviceSelector {
+    type Err = std::convert::Infallible;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(if s == "default" {
+            DeviceSelector::Default
+        } else if let Ok(i) = s.parse() {
+            DeviceSelector::Index(i)
+        } else {
+            DeviceSelector::Name(s.to_string())
+        })
+    }
+}
+
+impl DeviceSelector {
+    fn matches(&self, index: usize, name: &str) -> bool {
+        match self {
+            DeviceSelector::Default => true,
+            DeviceSelector::Name(n) => name.to_lowercase().contains(&n.to_lowercase()),
+            DeviceSelector::Index(i) => *i == index,
+        }
+    }
+}
+
+/// An input device and the configurations it supports.
+pub struct DeviceInfo {
+    pub index: usize,
+    pub name: String,
+    pub is_default: bool,
+    pub configs: Vec<cpal::SupportedStreamConfigRange>,
+}
+
+impl std::fmt::Display for DeviceInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{:3}: {}{}",
+            self.index,
+            self.name,
+            if self.is_default { " (default)" } else { "" }
+        )?;
+        if self.configs.is_empty() {
+            return writeln!(f, "     no supported input configurations");
+        }
+        writeln!(f, "     {:>8}  {:>15}  format", "channels", "rate (Hz)")?;
+        for c in self.configs.iter() {
+            let rate = if c.min_sample_rate() == c.max_sample_rate() {
+                format!("{}", c.min_sample_rate().0)
+            } else {
+                format!("{}-{}", c.min_sample_rate().0, c.max_sample_rate().0)
+            };
+            writeln!(
+                f,
+                "     {:>8}  {:>15}  {:?}",
+                c.channels(),
+                rate,
+                c.sample_format()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Describe every input device on the default host.
+pub fn list_input_devices() -> anyhow::Result<Vec<DeviceInfo>> {
+    let host = cpal::default_host();
+    let default_name = host.default_input_device().and_then(|d| d.name().ok());
+    let mut out = Vec::new();
+    for (index, device) in host.input_devices()?.enumerate() {
+        let name = device.name().unwrap_or_else(|_| "<unknown>".to_string());
+        let configs = device
+            .supported_input_configs()
+            .map(|c| c.collect())
+            .unwrap_or_default();
+        out.push(DeviceInfo {
+            index,
+            is_default: Some(&name) == default_name.as_ref(),
+            name,
+            configs,
+        });
+    }
+    Ok(out)
+}
+
+/// What the audio callbacks tell us.
+enum Captured {
+    Samples(Vec<i16>),
+    Error(cpal::StreamError),
+}
+
+/// If a device goes this long without giving us any audio, we assume it has
+/// gone away even if nobody told us so.
+const SILENT_DEVICE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Live audio from an input device, converted to 16 kHz mono.
+///
+/// If the device goes away, [`AudioSource::reconnect`] will keep trying to
+/// reopen it, or failing that the default device.  Once we are on the
+/// default device we stay there until it goes away too.
+pub struct Microphone {
+    selector: DeviceSelector,
+    samples: Receiver<Captured>,
+    // We hold onto the stream so it keeps playing.
+    _stream: cpal::Stream,
+}
+
+impl Microphone {
+    /// Listen to the default input device.
+    pub fn new() -> anyhow::Result<Self> {
+        Microphone::open(&DeviceSelector::Default)
+    }
+
+    pub fn open(selector: &DeviceSelector) -> anyhow::Result<Self> {
+        let (samples, stream) = start_stream(selector)?;
+        Ok(Microphone {
+            selector: selector.clone(),
+            samples,
+            _stream: stream,
+        })
+    }
+}
+
+fn start_stream(selector: &DeviceSelector) -> anyhow::Result<(Receiver<Captured>, cpal::Stream)> {
+    let (sender, samples) = channel();
+    let stream = open_input_stream(selector, sender)?;
+    stream.play().context("starting audio input")?;
+    Ok((samples, stream))
+}
+
+impl AudioSource for Microphone {
+    fn next_chunk(&mut self, events: &Events) -> anyhow::Result<Option<Vec<i16>>> {
+        loop {
+            match self.samples.recv_timeout(SILENT_DEVICE_TIMEOUT) {
+                Ok(Captured::Samples(chunk)) => return Ok(Some(chunk)),
+                Ok(Captured::Error(e @ cpal::StreamError::DeviceNotAvailable)) => {
+                    return Err(e).context("audio input stream error")
+                }
+                // The stream carries on, and if it doesn't we'll time out.
+                Ok(Captured::Error(e)) => {
+                    events.error(&anyhow::Error::new(e).context("audio input stream error"))
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(anyhow::anyhow!(
+                        "no audio input for {SILENT_DEVICE_TIMEOUT:?}"
+                    ))
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(anyhow::anyhow!("audio input stream has stopped"))
+                }
+            }
+        }
+    }
+
+    fn reconnect(&mut self, events: &Events) -> anyhow::Result<()> {
+        let mut backoff = Backoff::default();
+        loop {
+            let attempt = start_stream(&self.selector).or_else(|e| {
+                if self.selector == DeviceSelector::Default {
+                    Err(e)
+                } else {
+                    events.error(&e.context(format!("Unable to reopen {:?}", self.selector)));
+                    start_stream(&DeviceSelector::Default)
+                }
+            });
+            match attempt {
+                Ok((samples, stream)) => {
+                    self.samples = samples;
+                    self._stream = stream;
+                    return Ok(());
+                }
+                Err(e) => {
+                    let delay = backoff.next_delay();
+                    events.error(&e.context(format!(
+                        "Unable to open audio input.  Trying again in {delay:?}"
+                    )));
+                    std::thread::sleep(delay);
+                }
+            }
+        }
+    }
+}
+
+/// Exponentially growing delays between attempts to reopen a device.
+struct Backoff {
+    next: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            next: Duration::from_millis(250),
+        }
+    }
+}
+
+impl Backoff {
+    const MAX: Duration = Duration::from_secs(10);
+
+    fn next_delay(&mut self) -> Duration {
+        let delay = self.next;
+        self.next = std::cmp::min(2 * self.next, Backoff::MAX);
+        delay
+    }
+}
+
+fn build_stream(
+    device: cpal::Device,
+    config: cpal::SupportedStreamConfig,
+    sender: Sender<Captured>,
+) -> anyhow::Result<cpal::Stream> {
+    fn build<T: cpal::Sample>(
+        device: cpal::Device,
+        config: cpal::StreamConfig,
+        sender: Sender<Captured>,
+    ) -> Result<cpal::Stream, cpal::BuildStreamError> {
+        let mut converter = ToMono16kHz::new(config.sample_rate.0, config.channels);
+        let error_sender = sender.clone();
+        device.build_input_stream(
+            &config,
+            move |data: &[T], _: &cpal::InputCallbackInfo| {
+                sender.send(Captured::Samples(converter.convert(data))).ok();
+            },
+            move |err| {
+                error_sender.send(Captured::Error(err)).ok();
+            },
+        )
+    }
+    let stream = match config.sample_format() {
+        cpal::SampleFormat::I16 => build::<i16>(device, config.into(), sender),
+        cpal::SampleFormat::U16 => build::<u16>(device, config.into(), sender),
+        cpal::SampleFormat::F32 => build::<f32>(device, config.into(), sender),
+    };
+    stream.context("error creating stream")
+}
+
+/// Pick the config for `device` that needs the least conversion.
+fn choose_config(device: &cpal::Device) -> Option<cpal::SupportedStreamConfig> {
+    let configs: Vec<_> = device.supported_input_configs().ok()?.collect();
+    // Not having to resample matters most, then not having to downmix, and
+    // we'd rather not convert from floating point.
+    configs
+        .into_iter()
+        .filter(|c| c.min_sample_rate() <= REQUIRED_RATE && c.max_sample_rate() >= REQUIRED_RATE)
+        .min_by_key(|c| (c.channels(), c.sample_format() != cpal::SampleFormat::I16))
+        .map(|c| c.with_sample_rate(REQUIRED_RATE))
+        .or_else(|| device.default_input_config().ok())
+}
+
+fn open_input_stream(
+    selector: &DeviceSelector,
+    sender: Sender<Captured>,
+) -> anyhow::Result<cpal::Stream> {
+    let host = cpal::default_host();
+    if *selector == DeviceSelector::Default {
+        if let Some(device) = host.default_input_device() {
+            if let Some(config) = choose_config(&device) {
+                return build_stream(device, config, sender);
+            }
+        }
+    }
+    let mut names = Vec::new();
+    for (index, device) in host.input_devices()?.enumerate() {
+        let name = device.name().unwrap_or_default();
+        if selector.matches(index, &name) {
+            if let Some(config) = choose_config(&device) {
+                return build_stream(device, config, sender);
+            }
+        }
+        names.push(format!("{index}: {name}"));
+    }
+    if names.is_empty() {
+        anyhow::bail!("There are no audio input devices");
+    }
+    Err(anyhow::anyhow!(
+        "No usable input device matches {selector:?}.  We have:\n  {}",
+        names.join("\n  ")
+    ))
+}
+
+#[test]
+fn parse_device_selector() {
+    assert_eq!(Ok(DeviceSelector::Default), "default".parse());
+    assert_eq!(Ok(DeviceSelector::Index(2)), "2".parse());
+    assert_eq!(
+        Ok(DeviceSelector::Name("headset".to_string())),
+        "headset".parse()
+    );
+    assert!(DeviceSelector::Name("HeadSet".to_string()).matches(3, "USB headset mic"));
+    assert!(!DeviceSelector::Name("headset".to_string()).matches(3, "Built-in"));
+    assert!(DeviceSelector::Index(3).matches(3, "Built-in"));
+    assert!(!DeviceSelector::Index(2).matches(3, "Built-in"));
+}
+
+#[test]
+fn backoff() {
+    let mut backoff = Backoff::default();
+    let delays: Vec<_> = (0..8).map(|_| backoff.next_delay()).collect();
+    assert_eq!(delays[0], Duration::from_millis(250));
+    assert_eq!(delays[1], Duration::from_millis(500));
+    assert_eq!(delays[5], Duration::from_secs(8));
+    assert_eq!(delays[6], Backoff::MAX);
+    assert_eq!(delays[7], Backoff::MAX);
+}
@@ -0,0 +1,252 @@
+//! Conversion of whatever an input device gives us into 16 kHz mono.
+
+use std::f64::consts::PI;
+
+/// How many zero crossings of the sinc we keep on each side of its center.
+/// More gives a sharper cutoff at the price of more work per sample.
+const ZERO_CROSSINGS: f64 = 16.0;
+/// Where we put the cutoff, as a fraction of the lower of the two Nyquist
+/// frequencies, leaving room for the transition band.
+const ROLLOFF: f64 = 0.92;
+
+/// A streaming band-limited (windowed sinc) resampler.
+///
+/// Input may be fed in chunks of any size, and the output is the same as if
+/// it had all been given at once.  Output lags the input by the half-width
+/// of the filter, since each output sample needs to see a little of the
+/// future.
+pub struct Resampler {
+    /// Input samples per output sample.
+    step: f64,
+    /// Filter cutoff as a fraction of the input Nyquist frequency.
+    cutoff: f64,
+    /// Number of input samples on each side of the center of the filter.
+    half_width: usize,
+    history: Vec<f32>,
+    /// Position of the next output sample, in input samples from the start
+    /// of `history`.
+    position: f64,
+}
+
+impl Resampler {
+    pub fn new(input_rate: u32, output_rate: u32) -> Self {
+        let step = input_rate as f64 / output_rate as f64;
+        let cutoff = ROLLOFF * f64::min(1.0, 1.0 / step);
+        let half_width = if input_rate == output_rate {
+            0
+        } else {
+            (ZERO_CROSSINGS / cutoff).ceil() as usize
+        };
+        Resampler {
+            step,
+            cutoff,
+            half_width,
+            // Pretend the world was silent before we started listening.
+            history: vec![0.0; half_width],
+            position: half_width as f64,
+        }
+    }
+
+    /// Resample `input`, appending whatever output is ready to `output`.
+    pub fn process(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        if self.half_width == 0 {
+            output.extend_from_slice(input);
+            return;
+        }
+        self.history.extend_from_slice(input);
+        let hw = self.half_width as isize;
+        while self.position.floor() as isize + hw < self.history.len() as isize {
+            let center = self.position.floor() as isize;
+            let mut total = 0.0;
+            for k in center - hw + 1..=center + hw {
+                let x = self.position - k as f64;
+                total += self.history[k as usize] as f64 * self.kernel(x);
+            }
+            output.push(total as f32);
+            self.position += self.step;
+        }
+        // Forget the input that no future output sample can see.
+        let done = self.position.floor() as usize - self.half_width;
+        self.history.drain(..done);
+        self.position -= done as f64;
+    }
+
+    /// The filter response `x` input samples away from its center.
+    fn kernel(&self, x: f64) -> f64 {
+        let width = self.half_width as f64;
+        if x.abs() >= width {
+            return 0.0;
+        }
+        let sinc = if x == 0.0 {
+            1.0
+        } else {
+            let y = PI * self.cutoff * x;
+            y.sin() / y
+        };
+        // Blackman window
+        let w = x / width;
+        let window = 0.42 + 0.5 * (PI * w).cos() + 0.08 * (2.0 * PI * w).cos();
+        self.cutoff * sinc * window
+    }
+}
+
+/// Average interleaved channels down to mono.
+pub fn downmix(interleaved: &[f32], channels: usize, output: &mut Vec<f32>) {
+    if channels == 1 {
+        output.extend_from_slice(interleaved);
+    } else {
+        output.extend(
+            interleaved
+                .chunks_exact(channels)
+                .map(|frame| frame.iter().sum::<f32>() / channels as f32),
+        );
+    }
+}
+
+/// Converts interleaved samples of any format, rate and channel count into
+/// 16 kHz mono.
+pub struct ToMono16kHz {
+    channels: usize,
+    resampler: Resampler,
+    mono: Vec<f32>,
+    resampled: Vec<f32>,
+}
+
+impl ToMono16kHz {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        ToMono16kHz {
+            channels: channels as usize,
+            resampler: Resampler::new(sample_rate, crate::REQUIRED_RATE.0),
+            mono: Vec::new(),
+            resampled: Vec::new(),
+        }
+    }
+
+    pub fn convert<T: cpal::Sample>(&mut self, data: &[T]) -> Vec<i16> {
+        let floats: Vec<f32> = data.iter().map(|s| s.to_f32()).collect();
+        self.mono.clear();
+        downmix(&floats, self.channels, &mut self.mono);
+        self.resampled.clear();
+        self.resampler.process(&self.mono, &mut self.resampled);
+        self.resampled
+            .iter()
+            .map(|&f| (f.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+fn sine_sweep(rate: u32, seconds: f64, low: f64, high: f64) -> Vec<f32> {
+    let n = (rate as f64 * seconds) as usize;
+    (0..n)
+        .map(|i| {
+            let t = i as f64 / rate as f64;
+            let phase = 2.0 * PI * (low * t + (high - low) * t * t / (2.0 * seconds));
+            (0.5 * phase.sin()) as f32
+        })
+        .collect()
+}
+
+#[cfg(test)]
+fn rms(v: impl IntoIterator<Item = f32>) -> f64 {
+    let mut total = 0.0;
+    let mut n = 0;
+    for x in v {
+        total += x as f64 * x as f64;
+        n += 1;
+    }
+    (total / n as f64).sqrt()
+}
+
+#[cfg(test)]
+fn compare_with_sweep(input_rate: u32) {
+    let (low, high) = (100.0, 6000.0);
+    let input = sine_sweep(input_rate, 1.0, low, high);
+    let reference = sine_sweep(16_000, 1.0, low, high);
+
+    let mut resampler = Resampler::new(input_rate, 16_000);
+    let mut output = Vec::new();
+    resampler.process(&input, &mut output);
+    // The last half-width of the filter is still waiting for more input.
+    assert!(output.len() <= reference.len());
+    assert!(output.len() + 200 > reference.len());
+
+    // Skip the very beginning, where we assumed silence before the sweep.
+    let error = rms(output
+        .iter()
+        .zip(reference.iter())
+        .skip(100)
+        .map(|(a, b)| a - b));
+    println!("{input_rate} Hz: rms error {error}");
+    assert!(error < 0.005, "rms error {error} is too large");
+}
+
+#[test]
+fn resample_sweep_from_48k() {
+    compare_with_sweep(48_000);
+}
+
+#[test]
+fn resample_sweep_from_44k1() {
+    compare_with_sweep(44_100);
+}
+
+#[test]
+fn resample_sweep_from_8k() {
+    let input = sine_sweep(8_000, 1.0, 100.0, 3000.0);
+    let reference = sine_sweep(16_000, 1.0, 100.0, 3000.0);
+    let mut output = Vec::new();
+    Resampler::new(8_000, 16_000).process(&input, &mut output);
+    let error = rms(output
+        .iter()
+        .zip(reference.iter())
+        .skip(100)
+        .map(|(a, b)| a - b));
+    assert!(error < 0.005, "rms error {error} is too large");
+}
+
+#[test]
+fn resample_removes_frequencies_we_cannot_represent() {
+    // A 12 kHz tone is above the 8 kHz Nyquist frequency of our output.  Just
+    // taking every third sample would alias it down to 4 kHz.
+    let input: Vec<f32> = (0..48_000)
+        .map(|i| (0.5 * (2.0 * PI * 12_000.0 * i as f64 / 48_000.0).sin()) as f32)
+        .collect();
+    let aliased = rms(input.iter().step_by(3).copied());
+    let mut output = Vec::new();
+    Resampler::new(48_000, 16_000).process(&input, &mut output);
+    let filtered = rms(output.iter().skip(100).copied());
+    println!("aliased {aliased}, filtered {filtered}");
+    assert!(aliased > 0.3);
+    assert!(filtered < 0.001);
+}
+
+#[test]
+fn resample_in_chunks() {
+    let input = sine_sweep(44_100, 0.5, 100.0, 6000.0);
+    let mut all_at_once = Vec::new();
+    Resampler::new(44_100, 16_000).process(&input, &mut all_at_once);
+
+    let mut resampler = Resampler::new(44_100, 16_000);
+    let mut chunked = Vec::new();
+    for chunk in input.chunks(441) {
+        resampler.process(chunk, &mut chunked);
+    }
+    assert_eq!(all_at_once.len(), chunked.len());
+    for (a, b) in all_at_once.iter().zip(chunked.iter()) {
+        assert!((a - b).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn downmix_stereo() {
+    let mut mono = Vec::new();
+    downmix(&[1.0, 0.0, 0.5, 0.5, -1.0, 1.0], 2, &mut mono);
+    assert_eq!(mono, vec![0.5, 0.5, 0.0]);
+
+    let mut converter = ToMono16kHz::new(16_000, 2);
+    assert_eq!(
+        converter.convert(&[i16::MAX, i16::MAX, 0i16, 0, i16::MIN, 0]),
+        vec![i16::MAX, 0, -i16::MAX / 2]
+    );
+}
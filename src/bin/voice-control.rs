@@ -1,5 +1,324 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::{Parser, Subcommand};
+use voice_control::archive::{list_clips, ArchiveConfig};
+use voice_control::audio::{DeviceSelector, Microphone};
+use voice_control::choice::{AmbiguityPolicy, ChoiceConfig};
+use voice_control::corpus::{export_corpus, ExportConfig};
+use voice_control::events::{Events, LogFormat};
+use voice_control::listening::Listening;
+use voice_control::parser::generate::{GeneratorConfig, SentenceGenerator, Weighting};
 use voice_control::parser::IsParser;
-fn main() {
-    println!("{}", voice_control::parser::roundy::parser().describe());
-    voice_control::voice_control(voice_control::parser::roundy::parser);
+use voice_control::push_to_talk::{parse_key, Segmentation};
+use voice_control::recognizer::{Coqui, RecognizerConfig};
+use voice_control::regression::{cases_from_clips, read_manifest, run_regression, Report};
+use voice_control::review::{review_clips, INSTRUCTIONS};
+use voice_control::vad::{FrameLength, VadConfig, VadMode};
+
+/// Control your computer with your voice.
+#[derive(Parser)]
+struct Args {
+    /// Input device to listen to, by index or by part of its name.
+    #[clap(long, short, default_value = "default")]
+    device: DeviceSelector,
+
+    /// Speech model file, or a directory holding model.tflite and a scorer.
+    /// By default we look in the XDG data directories.
+    #[clap(long)]
+    model: Option<PathBuf>,
+
+    /// How aggressively to treat noise as silence: quality, low-bitrate,
+    /// aggressive or very-aggressive.
+    #[clap(long, default_value = "very-aggressive")]
+    vad_mode: VadMode,
+
+    /// Length of the frames the voice detector examines, in ms: 10, 20 or 30.
+    #[clap(long, default_value = "30")]
+    vad_frame: FrameLength,
+
+    /// Pause in ms that marks the end of a phrase.
+    #[clap(long, default_value = "250")]
+    silence: u64,
+
+    /// Phrases with less than this many ms of speech are ignored.
+    #[clap(long, default_value = "0")]
+    min_utterance: u64,
+
+    /// How many ms of audio from before speech starts to include.
+    #[clap(long, default_value = "250")]
+    pre_roll: u64,
+
+    /// Phrases longer than this many seconds are ignored.
+    #[clap(long, default_value = "15")]
+    max_utterance: u64,
+
+    /// Only listen while this key (e.g. F12 or ScrollLock) is held down.
+    #[clap(long, value_parser = parse_key, conflicts_with = "toggle")]
+    push_to_talk: Option<rdev::Key>,
+
+    /// Pressing this key turns listening on and off.
+    #[clap(long, value_parser = parse_key)]
+    toggle: Option<rdev::Key>,
+
+    /// How much more confident the best command must be than the next
+    /// best for it not to be ambiguous.
+    #[clap(long, default_value = "1.0")]
+    margin: f64,
+
+    /// What to do with ambiguous commands: take-best or reject.
+    #[clap(long, default_value = "take-best")]
+    ambiguity: AmbiguityPolicy,
+
+    /// How to log what is going on: human or json (one event per line).
+    #[clap(long, default_value = "human")]
+    log_format: LogFormat,
+
+    /// Keep what we hear in this directory, each clip with a JSON
+    /// description of what we made of it.
+    #[clap(long)]
+    archive: Option<PathBuf>,
+
+    /// Delete the oldest clips once the archive is bigger than this many MB.
+    #[clap(long, requires = "archive")]
+    archive_max_mb: Option<u64>,
+
+    /// Delete clips more than this many days old.
+    #[clap(long, requires = "archive")]
+    archive_max_days: Option<u64>,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List the available input devices and their supported configurations.
+    ListDevices,
+    /// Listen to archived clips we didn't recognize and say what was really
+    /// said.
+    Review {
+        /// The archive to review.
+        archive: PathBuf,
+        /// Review every clip nobody has corrected yet, not just the ones we
+        /// didn't recognize.
+        #[clap(long)]
+        all: bool,
+        /// Don't play the clips.
+        #[clap(long)]
+        no_play: bool,
+    },
+    /// Check how well we recognize labelled clips, listed in a manifest or
+    /// corrected in an archive, and what changed since a baseline.
+    Regression {
+        /// A manifest, or an archive directory.
+        manifest: PathBuf,
+        /// Results to compare against.
+        #[clap(long)]
+        baseline: Option<PathBuf>,
+        /// Save these results as the new baseline.
+        #[clap(long, requires = "baseline")]
+        save_baseline: bool,
+    },
+    /// Turn an archive of clips into CSV files and a text corpus for
+    /// training the speech model and its scorer.
+    ExportCorpus {
+        /// The archive to export.
+        archive: PathBuf,
+        /// Where to write the corpus.
+        output: PathBuf,
+        /// What fraction of clips to put in the dev set.
+        #[clap(long, default_value = "0.1")]
+        dev: f64,
+        /// What fraction of clips to put in the test set.
+        #[clap(long, default_value = "0.1")]
+        test: f64,
+        /// Also export clips nobody has corrected, using the transcript we
+        /// chose when we heard them.
+        #[clap(long)]
+        include_unreviewed: bool,
+    },
+    /// Write sentences from the command grammar to stdout, one per line, as
+    /// a corpus for building a scorer that knows our commands.
+    GenerateCorpus {
+        /// How many sentences to write.  If the grammar has no more than
+        /// this many, we write each of them once instead.
+        #[clap(long, default_value = "100000")]
+        sentences: usize,
+        /// The most times a repeated part of a command is repeated.
+        #[clap(long, default_value = "2")]
+        max_repeats: usize,
+        /// How to weigh alternatives: uniform or by-phrases.
+        #[clap(long, default_value = "uniform")]
+        weighting: Weighting,
+        #[clap(long, default_value = "0")]
+        seed: u64,
+    },
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    match args.command {
+        Some(Command::ListDevices) => {
+            for device in voice_control::audio::list_input_devices()? {
+                println!("{device}");
+            }
+            Ok(())
+        }
+        Some(Command::Review {
+            archive,
+            all,
+            no_play,
+        }) => {
+            let check = Listening::with_default_phrases(voice_control::parser::roundy::parser())
+                .to_full_diagnoser();
+            let play = |samples: &[i16]| {
+                if no_play {
+                    Ok(())
+                } else {
+                    voice_control::send_audio_output_16kHz(samples.to_vec())
+                }
+            };
+            println!("{INSTRUCTIONS}");
+            let summary = review_clips(
+                &list_clips(&archive)?,
+                all,
+                check,
+                play,
+                std::io::stdin().lock(),
+                std::io::stdout(),
+            )?;
+            println!(
+                "\nCorrected {} clips and skipped {}",
+                summary.corrected, summary.skipped
+            );
+            Ok(())
+        }
+        Some(Command::Regression {
+            manifest,
+            baseline,
+            save_baseline,
+        }) => {
+            let cases = if manifest.is_dir() {
+                cases_from_clips(&list_clips(&manifest)?)?
+            } else {
+                read_manifest(&manifest)?
+            };
+            let config = match &args.model {
+                Some(path) => RecognizerConfig::open(path)?.with_env_overrides()?,
+                None => RecognizerConfig::find()?,
+            };
+            let choice = ChoiceConfig {
+                margin: args.margin,
+                ambiguity: args.ambiguity,
+            };
+            let commands = voice_control::parser::roundy::parser;
+            let recognize =
+                voice_control::load_voice_control(Coqui::load(&config)?, &choice, commands)?;
+            let report = run_regression(&cases, &commands(), recognize)?;
+            let old = match &baseline {
+                Some(path) if !save_baseline => Some(Report::load(path)?),
+                _ => None,
+            };
+            print!("{}", report.describe(old.as_ref()));
+            if let (Some(path), true) = (&baseline, save_baseline) {
+                report.save(path)?;
+            }
+            if let Some(old) = &old {
+                let regressions = report.changes_since(old).regressions.len();
+                if regressions > 0 {
+                    anyhow::bail!("{regressions} clips regressed");
+                }
+            }
+            Ok(())
+        }
+        Some(Command::ExportCorpus {
+            archive,
+            output,
+            dev,
+            test,
+            include_unreviewed,
+        }) => {
+            let config = ExportConfig {
+                output,
+                dev,
+                test,
+                include_unreviewed,
+            };
+            let summary = export_corpus(&list_clips(&archive)?, &config)?;
+            println!(
+                "Exported {} training, {} dev and {} test clips to {:?}, skipping {}",
+                summary.train, summary.dev, summary.test, config.output, summary.skipped
+            );
+            Ok(())
+        }
+        Some(Command::GenerateCorpus {
+            sentences,
+            max_repeats,
+            weighting,
+            seed,
+        }) => {
+            let config = GeneratorConfig {
+                max_repeats,
+                weighting,
+            };
+            let generator =
+                SentenceGenerator::new(&voice_control::parser::roundy::parser(), &config);
+            let sentences = if generator.count() <= sentences as f64 {
+                generator.enumerate(sentences)
+            } else {
+                generator.sample(sentences, seed)
+            };
+            for sentence in sentences {
+                println!("{sentence}");
+            }
+            Ok(())
+        }
+        None => {
+            if args.log_format == LogFormat::Human {
+                println!("{}", voice_control::parser::roundy::parser().describe());
+            }
+            let vad = VadConfig {
+                mode: args.vad_mode,
+                frame: args.vad_frame,
+                silence: Duration::from_millis(args.silence),
+                min_utterance: Duration::from_millis(args.min_utterance),
+                pre_roll: Duration::from_millis(args.pre_roll),
+                max_utterance: Duration::from_secs(args.max_utterance),
+            };
+            let segmentation = if let Some(key) = args.push_to_talk {
+                Segmentation::PushToTalk(key, vad.max_utterance)
+            } else if let Some(key) = args.toggle {
+                Segmentation::Toggle(key, vad)
+            } else {
+                Segmentation::Vad(vad)
+            };
+            let options = voice_control::Options {
+                segmentation,
+                choice: ChoiceConfig {
+                    margin: args.margin,
+                    ambiguity: args.ambiguity,
+                },
+                events: Events::stdout(args.log_format),
+                archive: args.archive.map(|directory| ArchiveConfig {
+                    directory,
+                    max_bytes: args.archive_max_mb.map(|mb| mb * 1_000_000),
+                    max_age: args
+                        .archive_max_days
+                        .map(|days| Duration::from_secs(days * 24 * 60 * 60)),
+                }),
+            };
+            let config = match &args.model {
+                Some(path) => RecognizerConfig::open(path)?.with_env_overrides()?,
+                None => RecognizerConfig::find()?,
+            };
+            let microphone = Microphone::open(&args.device)?;
+            voice_control::voice_control_with_source(
+                microphone,
+                &options,
+                move || Coqui::load(&config),
+                voice_control::parser::roundy::parser,
+            )
+        }
+    }
 }
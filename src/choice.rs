@@ -0,0 +1,127 @@
+//! Deciding which of the recognizer's guesses to believe.
+
+use crate::recognition::Hypothesis;
+
+/// What to do when two different valid transcripts are too close to call.
+/// Transcripts are compared by their text, so two wordings of the same
+/// command still count as ambiguous.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AmbiguityPolicy {
+    /// Go with the more confident one.
+    #[default]
+    TakeBest,
+    /// Do nothing, rather than risk doing the wrong thing.
+    Reject,
+}
+
+impl std::str::FromStr for AmbiguityPolicy {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "take-best" => Ok(AmbiguityPolicy::TakeBest),
+            "reject" => Ok(AmbiguityPolicy::Reject),
+            _ => Err(anyhow::anyhow!(
+                "The ambiguity policy must be take-best or reject, not {s:?}"
+            )),
+        }
+    }
+}
+
+/// How we pick a transcript.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChoiceConfig {
+    /// If the best valid transcript isn't more confident than the next
+    /// valid one by at least this much, it is ambiguous.
+    pub margin: f64,
+    pub ambiguity: AmbiguityPolicy,
+}
+
+impl Default for ChoiceConfig {
+    fn default() -> Self {
+        ChoiceConfig {
+            margin: 1.0,
+            ambiguity: AmbiguityPolicy::TakeBest,
+        }
+    }
+}
+
+/// Pick the most confident of `hypotheses` (which are most confident
+/// first) that is a whole command, returning its index and whether it was
+/// ambiguous.
+pub(crate) fn choose(hypotheses: &[Hypothesis], config: &ChoiceConfig) -> (Option<usize>, bool) {
+    let mut valid = hypotheses
+        .iter()
+        .enumerate()
+        .filter(|(_, h)| h.outcome.is_complete());
+    let (best, chosen) = match valid.next() {
+        Some(best) => best,
+        None => return (None, false),
+    };
+    let ambiguous = valid
+        .find(|(_, h)| h.text != chosen.text)
+        .is_some_and(|(_, h)| chosen.confidence - h.confidence < config.margin);
+    if ambiguous && config.ambiguity == AmbiguityPolicy::Reject {
+        (None, true)
+    } else {
+        (Some(best), ambiguous)
+    }
+}
+
+#[cfg(test)]
+fn numbers(guesses: &[(&str, f64)]) -> Vec<Hypothesis> {
+    use crate::recognition::Outcome;
+
+    guesses
+        .iter()
+        .map(|&(text, confidence)| Hypothesis {
+            text: text.to_string(),
+            confidence,
+            outcome: if text.parse::<u32>().is_ok() {
+                Outcome::Complete
+            } else {
+                Outcome::Wrong
+            },
+        })
+        .collect()
+}
+
+#[test]
+fn choose_a_valid_hypothesis() {
+    let guesses = numbers(&[("for", -1.0), ("fore", -2.0), ("4", -5.0)]);
+    let config = ChoiceConfig::default();
+    assert_eq!((Some(2), false), choose(&guesses, &config));
+    assert_eq!((None, false), choose(&guesses[..2], &config));
+    assert_eq!((None, false), choose(&[], &config));
+}
+
+#[test]
+fn ambiguous_hypotheses() {
+    let guesses = numbers(&[("5", -1.0), ("9", -1.5), ("nine", -1.7)]);
+    let take_best = ChoiceConfig::default();
+    assert_eq!((Some(0), true), choose(&guesses, &take_best));
+
+    let reject = ChoiceConfig {
+        ambiguity: AmbiguityPolicy::Reject,
+        ..ChoiceConfig::default()
+    };
+    assert_eq!((None, true), choose(&guesses, &reject));
+
+    let confident = ChoiceConfig {
+        margin: 0.25,
+        ..reject
+    };
+    assert_eq!((Some(0), false), choose(&guesses, &confident));
+
+    // Saying the same thing twice isn't ambiguous.
+    let guesses = numbers(&[("5", -1.0), ("5", -1.1)]);
+    assert_eq!((Some(0), false), choose(&guesses, &reject));
+}
+
+#[test]
+fn parse_ambiguity_policy() {
+    assert_eq!(
+        AmbiguityPolicy::Reject,
+        "reject".parse::<AmbiguityPolicy>().unwrap()
+    );
+    assert!("maybe".parse::<AmbiguityPolicy>().is_err());
+}
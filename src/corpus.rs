@@ -0,0 +1,228 @@
+//! Turning the [archive](crate::archive) into training data for the speech
+//! model and its scorer.
+//!
+//! We write Coqui/DeepSpeech style CSV files (`train.csv`, `dev.csv` and
+//! `test.csv`, each with `wav_filename,wav_filesize,transcript` columns),
+//! plus `corpus.txt`, the training transcripts one per line, for building a
+//! language model.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+use crate::archive::Clip;
+
+/// How to export a corpus.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportConfig {
+    /// The directory to write the corpus to.
+    pub output: PathBuf,
+    /// What fraction of clips go in the dev set.
+    pub dev: f64,
+    /// What fraction of clips go in the test set.
+    pub test: f64,
+    /// Also use clips nobody has corrected, trusting the transcript we
+    /// chose at the time.
+    pub include_unreviewed: bool,
+}
+
+impl ExportConfig {
+    pub fn new(output: impl Into<PathBuf>) -> Self {
+        ExportConfig {
+            output: output.into(),
+            dev: 0.1,
+            test: 0.1,
+            include_unreviewed: false,
+        }
+    }
+}
+
+/// How many clips ended up where.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExportSummary {
+    pub train: usize,
+    pub dev: usize,
+    pub test: usize,
+    /// Clips with no transcript we trust, or one the model can't spell.
+    pub skipped: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Split {
+    Train,
+    Dev,
+    Test,
+}
+
+/// Export `clips` as a corpus, as `config` says.
+pub fn export_corpus(clips: &[Clip], config: &ExportConfig) -> anyhow::Result<ExportSummary> {
+    let output = &config.output;
+    std::fs::create_dir_all(output).with_context(|| format!("creating {output:?}"))?;
+    let create = |name: &str| -> anyhow::Result<std::io::BufWriter<std::fs::File>> {
+        let path = output.join(name);
+        let file = std::fs::File::create(&path).with_context(|| format!("creating {path:?}"))?;
+        Ok(std::io::BufWriter::new(file))
+    };
+    let mut train = create("train.csv")?;
+    let mut dev = create("dev.csv")?;
+    let mut test = create("test.csv")?;
+    let mut corpus = create("corpus.txt")?;
+    for csv in [&mut train, &mut dev, &mut test] {
+        writeln!(csv, "wav_filename,wav_filesize,transcript")?;
+    }
+
+    let mut summary = ExportSummary::default();
+    for clip in clips {
+        let metadata = clip.metadata()?;
+        let transcript = match &metadata.correction {
+            Some(correction) => Some(correction.as_str()),
+            None if config.include_unreviewed => metadata.chosen_text(),
+            None => None,
+        };
+        let transcript = match transcript.and_then(normalize) {
+            Some(transcript) => transcript,
+            None => {
+                summary.skipped += 1;
+                continue;
+            }
+        };
+        let wav = clip
+            .wav
+            .canonicalize()
+            .with_context(|| format!("finding {:?}", clip.wav))?;
+        let size = wav.metadata()?.len();
+        let csv = match split(&clip.wav, config) {
+            Split::Train => {
+                summary.train += 1;
+                writeln!(corpus, "{transcript}")?;
+                &mut train
+            }
+            Split::Dev => {
+                summary.dev += 1;
+                &mut dev
+            }
+            Split::Test => {
+                summary.test += 1;
+                &mut test
+            }
+        };
+        writeln!(csv, "{},{size},{transcript}", csv_field(&wav))?;
+    }
+    for out in [&mut train, &mut dev, &mut test, &mut corpus] {
+        out.flush()?;
+    }
+    Ok(summary)
+}
+
+/// Which set a clip belongs in.  This depends only on its name, so clips
+/// stay put as the archive grows, and nothing we test on is ever trained on.
+fn split(wav: &Path, config: &ExportConfig) -> Split {
+    let name = wav.file_name().unwrap_or_default().to_string_lossy();
+    let x = (crate::fnv1a(name.bytes()) % 1_000_000) as f64 / 1_000_000.0;
+    if x < config.test {
+        Split::Test
+    } else if x < config.test + config.dev {
+        Split::Dev
+    } else {
+        Split::Train
+    }
+}
+
+/// Put a transcript in the form the model is trained on: lower case words
+/// separated by single spaces.  Returns `None` if it has anything but
+/// letters and apostrophes in it.
+fn normalize(transcript: &str) -> Option<String> {
+    let words: Vec<String> = transcript
+        .split_whitespace()
+        .map(|w| w.to_lowercase())
+        .collect();
+    if words.is_empty()
+        || words
+            .iter()
+            .any(|w| !w.chars().all(|c| c.is_ascii_lowercase() || c == '\''))
+    {
+        return None;
+    }
+    Some(words.join(" "))
+}
+
+fn csv_field(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    if path.contains([',', '"', '\n']) {
+        format!("\"{}\"", path.replace('"', "\"\""))
+    } else {
+        path.into_owned()
+    }
+}
+
+#[test]
+fn normalize_transcripts() {
+    assert_eq!(
+        normalize("  Testing   ONE two "),
+        Some("testing one two".into())
+    );
+    assert_eq!(normalize("don't"), Some("don't".into()));
+    assert_eq!(normalize("5 blind mice"), None);
+    assert_eq!(normalize(""), None);
+}
+
+#[test]
+fn clips_stay_in_their_sets() {
+    let config = ExportConfig::new("corpus");
+    let sets: Vec<Split> = [
+        "000000-testing.wav",
+        "000006-testing.wav",
+        "000024-testing.wav",
+    ]
+    .iter()
+    .map(|name| split(&Path::new("archive").join(name), &config))
+    .collect();
+    assert_eq!(sets, [Split::Train, Split::Dev, Split::Test]);
+}
+
+#[test]
+fn export_archive() {
+    use crate::archive::{metadata, Archive, ArchiveConfig};
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut archive = Archive::open(&ArchiveConfig::new(dir.path().join("archive"))).unwrap();
+    let mut save = |heard: &str, correction: Option<&str>| {
+        let metadata = metadata(&[heard], Some(0), correction);
+        archive.save(&[1, 2, 3], heard, &metadata).unwrap();
+    };
+    for _ in 0..50 {
+        save("testing", Some("testing testing"));
+    }
+    save("go to sleep", None);
+    save("one up", Some("1 up"));
+
+    let mut config = ExportConfig::new(dir.path().join("corpus"));
+    let clips = archive.clips().unwrap();
+    let summary = export_corpus(&clips, &config).unwrap();
+    assert_eq!(summary.skipped, 2);
+    assert_eq!(summary.train + summary.dev + summary.test, 50);
+    assert!(summary.train > summary.dev + summary.test);
+    assert!(summary.dev > 0 && summary.test > 0, "{summary:?}");
+
+    let train = std::fs::read_to_string(config.output.join("train.csv")).unwrap();
+    let mut lines = train.lines();
+    assert_eq!(lines.next(), Some("wav_filename,wav_filesize,transcript"));
+    let row: Vec<&str> = lines.next().unwrap().split(',').collect();
+    assert!(Path::new(row[0]).is_absolute());
+    assert_eq!(row[1], "50");
+    assert_eq!(row[2], "testing testing");
+    let corpus = std::fs::read_to_string(config.output.join("corpus.txt")).unwrap();
+    assert_eq!(corpus.lines().count(), summary.train);
+
+    // The same clips land in the same sets every time.
+    config.include_unreviewed = true;
+    let again = export_corpus(&clips, &config).unwrap();
+    assert_eq!(again.skipped, 1);
+    assert_eq!(
+        again.train + again.dev + again.test,
+        summary.train + summary.dev + summary.test + 1
+    );
+    let corpus = std::fs::read_to_string(config.output.join("corpus.txt")).unwrap();
+    assert_eq!(corpus.contains("go to sleep"), again.train > summary.train);
+}
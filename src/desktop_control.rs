@@ -19,6 +19,10 @@ impl Action {
         }
     }
 
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     pub fn only_log(input: &str) -> Self {
         let input = input.to_string();
         Action {
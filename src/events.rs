@@ -0,0 +1,357 @@
+//! What is going on as we listen, for whoever wants to know.
+//!
+//! The library doesn't print anything itself.  Instead it sends [`Event`]s
+//! to [`Events`], which passes them on to every [`Observer`] that has
+//! subscribed, such as a [`HumanLogger`] or a [`JsonLogger`].
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+
+use crate::recognition::Hypothesis;
+
+/// Something that happened.  Times are in seconds, measured from the start
+/// of the audio.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// We have been listening for this long.
+    AudioProgress {
+        seconds: f64,
+    },
+    /// The audio source failed, and we are trying to get it back.
+    AudioLost {
+        error: String,
+    },
+    AudioBack,
+    /// Someone started talking.
+    UtteranceStarted {
+        start: f64,
+    },
+    /// They finished.
+    UtteranceEnded {
+        start: f64,
+        end: f64,
+    },
+    /// They went on so long that we threw it all away, or it was over so
+    /// quickly that it was probably just a click or a cough.
+    UtteranceAbandoned {
+        start: f64,
+        end: f64,
+    },
+    /// What the recognizer has made of the utterance so far.
+    Partial {
+        text: String,
+    },
+    /// Everything the recognizer thought was said, most confident first.
+    Hypotheses {
+        hypotheses: Vec<Hypothesis>,
+        /// How long the utterance was.
+        audio: f64,
+        /// How long we waited for the recognizer once it was over.
+        recognizing: f64,
+    },
+    /// The hypothesis we went with.
+    Chosen {
+        text: String,
+        confidence: f64,
+        ambiguous: bool,
+    },
+    /// None of the hypotheses made sense, or they were too close to call.
+    Unrecognized {
+        ambiguous: bool,
+    },
+    ActionExecuted {
+        action: String,
+    },
+    Asleep,
+    Awake,
+    /// Listening was switched on or off from the keyboard.
+    ListeningToggled {
+        on: bool,
+    },
+    /// An utterance was saved.
+    Saved {
+        path: PathBuf,
+        samples: usize,
+    },
+    /// An old utterance was deleted to make room.
+    Pruned {
+        path: PathBuf,
+    },
+    Error {
+        message: String,
+    },
+}
+
+/// Something that wants to hear about [`Event`]s.
+pub trait Observer: Send {
+    fn observe(&mut self, event: &Event);
+}
+
+impl<F: Send + FnMut(&Event)> Observer for F {
+    fn observe(&mut self, event: &Event) {
+        self(event)
+    }
+}
+
+/// Hands events to everyone who has subscribed.
+///
+/// Clones share their subscribers, so events can be sent from any thread.
+#[derive(Clone, Default)]
+pub struct Events {
+    observers: Arc<Mutex<Vec<Box<dyn Observer>>>>,
+}
+
+impl std::fmt::Debug for Events {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let observers = self.observers.lock().map_or(0, |o| o.len());
+        f.debug_struct("Events")
+            .field("observers", &observers)
+            .finish()
+    }
+}
+
+impl Events {
+    pub fn new() -> Self {
+        Events::default()
+    }
+
+    /// Events that are logged to stdout.
+    pub fn stdout(format: LogFormat) -> Self {
+        let events = Events::new();
+        match format {
+            LogFormat::Human => events.subscribe(HumanLogger::new(std::io::stdout())),
+            LogFormat::Json => events.subscribe(JsonLogger::new(std::io::stdout())),
+        }
+        events
+    }
+
+    pub fn subscribe(&self, observer: impl 'static + Observer) {
+        if let Ok(mut observers) = self.observers.lock() {
+            observers.push(Box::new(observer));
+        }
+    }
+
+    pub fn emit(&self, event: Event) {
+        if let Ok(mut observers) = self.observers.lock() {
+            for observer in observers.iter_mut() {
+                observer.observe(&event);
+            }
+        }
+    }
+
+    pub fn error(&self, error: &anyhow::Error) {
+        self.emit(Event::Error {
+            message: format!("{error:#}"),
+        })
+    }
+}
+
+/// How events are written to a log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// A [`HumanLogger`].
+    Human,
+    /// A [`JsonLogger`].
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(LogFormat::Human),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(anyhow::anyhow!(
+                "The log format must be human or json, not {s:?}"
+            )),
+        }
+    }
+}
+
+/// Describes events in plain English.
+pub struct HumanLogger<W> {
+    out: W,
+}
+
+impl<W: Write + Send> HumanLogger<W> {
+    pub fn new(out: W) -> Self {
+        HumanLogger { out }
+    }
+
+    fn log(&mut self, event: &Event) -> std::io::Result<()> {
+        let out = &mut self.out;
+        match event {
+            Event::AudioProgress { seconds } => writeln!(out, "It has been {seconds:.1} seconds")?,
+            Event::AudioLost { error } => writeln!(out, "Lost audio input: {error}")?,
+            Event::AudioBack => writeln!(out, "Audio input is back")?,
+            Event::UtteranceStarted { .. } | Event::UtteranceEnded { .. } => (),
+            Event::UtteranceAbandoned { start, end } => {
+                writeln!(out, "Ignoring {:.1} seconds of audio", end - start)?
+            }
+            Event::Partial { text } => writeln!(out, "    ...{text}")?,
+            Event::Hypotheses {
+                hypotheses,
+                audio,
+                recognizing,
+            } => {
+                if hypotheses.iter().all(|h| h.text.is_empty()) {
+                    writeln!(out, "You didn't say anything")?;
+                } else {
+                    for h in hypotheses {
+                        let mark = if h.outcome.is_complete() { "OK" } else { "  " };
+                        writeln!(
+                            out,
+                            "   {mark} {:.2}: {:?} {:?}",
+                            h.confidence, h.text, h.outcome
+                        )?;
+                    }
+                    writeln!(
+                        out,
+                        "   Recognized {audio:.1} seconds of speech in {:.0} ms",
+                        recognizing * 1000.0
+                    )?;
+                }
+            }
+            Event::Chosen {
+                text, ambiguous, ..
+            } => {
+                if *ambiguous {
+                    writeln!(out, "=> {text:?}, though that was close")?;
+                } else {
+                    writeln!(out, "=> {text:?}")?;
+                }
+            }
+            Event::Unrecognized { ambiguous: true } => writeln!(out, "That was too close to call")?,
+            Event::Unrecognized { ambiguous: false } => (),
+            Event::ActionExecuted { action } => writeln!(out, "Running {action}")?,
+            Event::Asleep => writeln!(out, "Going to sleep.  Say \"wake up\" to resume.")?,
+            Event::Awake => writeln!(out, "Awake and listening for commands")?,
+            Event::ListeningToggled { on: true } => writeln!(out, "Listening")?,
+            Event::ListeningToggled { on: false } => writeln!(out, "Not listening")?,
+            Event::Saved { path, samples } => {
+                writeln!(out, "Saved {samples} samples as {}", path.display())?
+            }
+            Event::Pruned { path } => writeln!(out, "Deleted {}", path.display())?,
+            Event::Error { message } => writeln!(out, "{message}")?,
+        }
+        out.flush()
+    }
+}
+
+impl<W: Write + Send> Observer for HumanLogger<W> {
+    fn observe(&mut self, event: &Event) {
+        // If we can't log, there is nobody to tell.
+        self.log(event).ok();
+    }
+}
+
+/// Writes each event as a line of JSON, for other programs to consume.
+pub struct JsonLogger<W> {
+    out: W,
+}
+
+impl<W: Write + Send> JsonLogger<W> {
+    pub fn new(out: W) -> Self {
+        JsonLogger { out }
+    }
+}
+
+impl<W: Write + Send> Observer for JsonLogger<W> {
+    fn observe(&mut self, event: &Event) {
+        if serde_json::to_writer(&mut self.out, event).is_ok() {
+            writeln!(self.out).ok();
+            self.out.flush().ok();
+        }
+    }
+}
+
+#[cfg(test)]
+fn example_events() -> Vec<Event> {
+    use crate::recognition::Outcome;
+
+    vec![
+        Event::UtteranceStarted { start: 1.5 },
+        Event::UtteranceEnded {
+            start: 1.5,
+            end: 2.25,
+        },
+        Event::Hypotheses {
+            hypotheses: vec![
+                Hypothesis {
+                    text: "testing one".to_string(),
+                    confidence: -1.0,
+                    outcome: Outcome::ExtraWords("one".to_string()),
+                },
+                Hypothesis {
+                    text: "testing".to_string(),
+                    confidence: -2.5,
+                    outcome: Outcome::Complete,
+                },
+            ],
+            audio: 0.75,
+            recognizing: 0.05,
+        },
+        Event::Chosen {
+            text: "testing".to_string(),
+            confidence: -2.5,
+            ambiguous: false,
+        },
+        Event::ActionExecuted {
+            action: "\"Testing!\"".to_string(),
+        },
+        Event::Asleep,
+    ]
+}
+
+#[test]
+fn human_logger() {
+    let mut logger = HumanLogger::new(Vec::new());
+    for event in example_events() {
+        logger.observe(&event);
+    }
+    let expected = expect_test::expect![[r#"
+              -1.00: "testing one" ExtraWords("one")
+           OK -2.50: "testing" Complete
+           Recognized 0.8 seconds of speech in 50 ms
+        => "testing"
+        Running "Testing!"
+        Going to sleep.  Say "wake up" to resume.
+    "#]];
+    expected.assert_eq(&String::from_utf8(logger.out).unwrap());
+}
+
+#[test]
+fn json_logger() {
+    let mut logger = JsonLogger::new(Vec::new());
+    for event in example_events() {
+        logger.observe(&event);
+    }
+    let expected = expect_test::expect![[r#"
+        {"event":"utterance_started","start":1.5}
+        {"event":"utterance_ended","start":1.5,"end":2.25}
+        {"event":"hypotheses","hypotheses":[{"text":"testing one","confidence":-1.0,"outcome":{"extra_words":"one"}},{"text":"testing","confidence":-2.5,"outcome":"complete"}],"audio":0.75,"recognizing":0.05}
+        {"event":"chosen","text":"testing","confidence":-2.5,"ambiguous":false}
+        {"event":"action_executed","action":"\"Testing!\""}
+        {"event":"asleep"}
+    "#]];
+    expected.assert_eq(&String::from_utf8(logger.out).unwrap());
+}
+
+#[test]
+fn subscribers_share_events() {
+    let heard = Arc::new(Mutex::new(Vec::new()));
+    let events = Events::new();
+    let h = heard.clone();
+    events.subscribe(move |e: &Event| h.lock().unwrap().push(e.clone()));
+    // Events sent from anywhere reach every subscriber.
+    let other = events.clone();
+    std::thread::spawn(move || other.emit(Event::AudioBack))
+        .join()
+        .unwrap();
+    events.emit(Event::Awake);
+    assert_eq!(*heard.lock().unwrap(), [Event::AudioBack, Event::Awake]);
+}
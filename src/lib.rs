@@ -1,227 +1,101 @@
+pub mod archive;
+pub mod audio;
+pub mod choice;
+pub mod corpus;
 pub mod keys;
 pub mod parser;
 
 // pub mod keys;
 
 pub mod desktop_control;
+pub mod events;
+pub mod listening;
+pub mod push_to_talk;
+pub mod recognition;
+pub mod recognizer;
+pub mod regression;
+pub mod review;
+pub mod streaming;
+pub mod vad;
+use std::sync::Arc;
+use std::time::Instant;
+
+use archive::{Archive, ArchiveConfig, ClipMetadata};
+use audio::{AudioSource, Chunk};
+use choice::ChoiceConfig;
 use desktop_control::Action;
-use parser::{Error, IsParser, Parser};
+use events::{Event, Events};
+use listening::{Heard, Listening, ListeningState};
+use parser::Parser;
+use push_to_talk::Segmentation;
+use recognition::{Recognition, Timing};
+use recognizer::{Checker, SpeechRecognizer, Transcript};
+use streaming::StreamingRecognizer;
+use vad::{SegmentEvent, Utterance};
 
-const VAD_SAMPLES: u32 = 16 * 30; // 30 ms at 16 kHz.  10 and 20 are also options.
 const RATE_AS_USIZE: usize = 16_000;
 const REQUIRED_RATE: cpal::SampleRate = cpal::SampleRate(RATE_AS_USIZE as u32);
-// Time to wait between phrases.  Let's wait a quarter second.
-const SILENCE_BETWEEN_PHRASES: usize = RATE_AS_USIZE / 4;
+/// Play 16 kHz mono `samples` on the first output device that will take
+/// them, returning once they have been played.
 #[allow(non_snake_case)]
-fn get_audio_input_16kHz<F: FnMut(&[i16]) + Send + 'static>(mut callback: F) -> ! {
-    const THREE_RATE: cpal::SampleRate = cpal::SampleRate(3 * REQUIRED_RATE.0);
-    use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-    let use_config = move |device: cpal::Device, config: cpal::SupportedStreamConfig| -> ! {
-        if config.sample_format() == cpal::SampleFormat::I16
-            && config.sample_rate() == REQUIRED_RATE
-        {
-            let stream = device
-                .build_input_stream(
-                    &config.into(),
-                    move |data: &[i16], _: &cpal::InputCallbackInfo| callback(data),
-                    move |err| {
-                        // react to errors here.
-                        panic!("stream error: {}", err);
-                    },
-                )
-                .expect("error creating stream");
-            stream.play().unwrap();
-            loop {
-                std::thread::sleep(std::time::Duration::from_secs_f64(1.0e3));
-            }
-        } else if config.sample_format() == cpal::SampleFormat::F32
-            && config.sample_rate() == REQUIRED_RATE
-        {
-            println!("Running with f32... at 16 kHz");
-            let mut ints = Vec::new();
-            let stream = device
-                .build_input_stream(
-                    &config.into(),
-                    move |data: &[f32], _: &cpal::InputCallbackInfo| {
-                        ints.clear();
-                        ints.extend(
-                            data.iter()
-                                .copied()
-                                .map(|f| (f * (i16::MAX as f32 - 1.0)) as i16),
-                        );
-                        callback(&ints);
-                    },
-                    move |err| {
-                        // react to errors here.
-                        panic!("stream error: {}", err);
-                    },
-                )
-                .expect("error creating stream");
-            stream.play().unwrap();
-            loop {
-                std::thread::sleep(std::time::Duration::from_secs_f64(1.0e3));
-            }
-        } else if config.sample_format() == cpal::SampleFormat::F32
-            && config.sample_rate() == THREE_RATE
-        {
-            let mut ints = Vec::new();
-            println!("running at higher hz");
-            let stream = device
-                .build_input_stream(
-                    &config.into(),
-                    move |data: &[f32], _: &cpal::InputCallbackInfo| {
-                        ints.clear();
-                        ints.extend(
-                            data.iter()
-                                .step_by(3)
-                                .copied()
-                                .map(|f| (f * (i16::MAX as f32 - 1.0)) as i16),
-                        );
-                        callback(&ints);
-                    },
-                    move |err| {
-                        // react to errors here.
-                        panic!("stream error: {}", err);
-                    },
-                )
-                .expect("error creating stream");
-            stream.play().unwrap();
-            loop {
-                std::thread::sleep(std::time::Duration::from_secs_f64(1.0e3));
-            }
-        } else {
-            panic!("Unsupported configuration!");
-        }
-    };
-    let host = cpal::default_host();
-    for device in host.input_devices().unwrap() {
-        println!("\ndevice is {:?}\n", device.name());
-        let supported_configs_range = device
-            .supported_input_configs()
-            .expect("error while querying configs");
-        if let Some(supported_config_range) = supported_configs_range
-            .filter(|c| c.channels() == 1)
-            .filter(|c| c.sample_format() == cpal::SampleFormat::I16)
-            .filter(|c| c.min_sample_rate() <= REQUIRED_RATE)
-            .filter(|c| c.max_sample_rate() >= REQUIRED_RATE)
-            .next()
-        {
-            use_config(
-                device,
-                supported_config_range.with_sample_rate(REQUIRED_RATE),
-            );
-        }
-    }
-    println!("No device supports i16 sampling");
-    for device in host.input_devices().unwrap() {
-        let supported_configs_range = device
-            .supported_input_configs()
-            .expect("error while querying configs");
-        if let Some(supported_config_range) = supported_configs_range
-            .filter(|c| c.channels() == 1)
-            // .filter(|c| c.sample_format() == cpal::SampleFormat::F32)
-            .filter(|c| c.min_sample_rate() <= REQUIRED_RATE)
-            .filter(|c| c.max_sample_rate() >= REQUIRED_RATE)
-            .next()
-        {
-            use_config(
-                device,
-                supported_config_range.with_sample_rate(REQUIRED_RATE),
-            );
-        }
-    }
-    println!("No device supports f32 sampling at 16 kHz");
-    for device in host.input_devices().unwrap() {
-        let supported_configs_range = device
-            .supported_input_configs()
-            .expect("error while querying configs");
-        if let Some(supported_config_range) = supported_configs_range
-            .filter(|c| c.channels() == 1)
-            // .filter(|c| c.sample_format() == cpal::SampleFormat::F32)
-            .filter(|c| c.min_sample_rate() <= THREE_RATE)
-            .filter(|c| c.max_sample_rate() >= THREE_RATE)
-            .next()
-        {
-            use_config(device, supported_config_range.with_sample_rate(THREE_RATE));
-        }
-    }
-    println!("No device supports f32 sampling at 8*16 kHz");
-    for device in host.input_devices().unwrap() {
-        println!("\ndevice is {:?}\n", device.name());
-        let supported_configs_range = device
-            .supported_input_configs()
-            .expect("error while querying configs");
-        for scr in supported_configs_range {
-            println!("   {:?}", scr);
-        }
-    }
-    panic!("No supported audio config!");
-}
-
-#[allow(non_snake_case)]
-fn send_audio_output_16kHz(mut samples: Vec<i16>) -> anyhow::Result<()> {
+pub fn send_audio_output_16kHz(mut samples: Vec<i16>) -> anyhow::Result<()> {
     use anyhow::Context;
     use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
     use cpal::Sample;
     samples.reverse();
+    let duration =
+        std::time::Duration::from_secs_f64(0.1 + samples.len() as f64 / REQUIRED_RATE.0 as f64);
     let host = cpal::default_host();
-    for device in host.output_devices().unwrap() {
-        println!("\ndevice is {:?}\n", device.name());
+    for device in host.output_devices()? {
         let supported_configs_range = device.supported_output_configs()?;
         if let Some(supported_config_range) = supported_configs_range
-            // .filter(|c| c.channels() == 1)
             .filter(|c| c.sample_format() == cpal::SampleFormat::I16)
-            // .filter(|c| c.min_sample_rate() <= REQUIRED_RATE)
-            // .filter(|c| c.max_sample_rate() >= REQUIRED_RATE)
+            .filter(|c| c.min_sample_rate() <= REQUIRED_RATE)
+            .filter(|c| c.max_sample_rate() >= REQUIRED_RATE)
             .next()
         {
             let config = supported_config_range.with_sample_rate(REQUIRED_RATE);
+            let channels = config.channels() as usize;
             let stream = device.build_output_stream(
                 &config.into(),
                 move |data: &mut [i16], _| {
-                    if samples.is_empty() {
-                        panic!("all done")
-                    }
-                    for v in data.iter_mut() {
-                        *v = samples.pop().unwrap_or_default();
+                    for frame in data.chunks_mut(channels) {
+                        let value = samples.pop().unwrap_or_default();
+                        for v in frame.iter_mut() {
+                            *v = value;
+                        }
                     }
                 },
-                |_| println!("had error"),
+                |e| eprintln!("Error playing audio: {e}"),
             )?;
-            return stream.play().context("Playing stream");
+            stream.play().context("Playing stream")?;
+            std::thread::sleep(duration);
+            return Ok(());
         }
     }
-    println!("No device supports i16 sampling");
 
-    for device in host.output_devices().unwrap() {
-        println!("\ndevice is {:?}\n", device.name());
+    for device in host.output_devices()? {
         let supported_configs_range = device.supported_output_configs()?;
         if let Some(supported_config_range) = supported_configs_range
-            // .filter(|c| c.channels() == 1)
             .filter(|c| c.sample_format() == cpal::SampleFormat::F32)
             .filter(|c| c.min_sample_rate() <= REQUIRED_RATE)
             .filter(|c| c.max_sample_rate() >= REQUIRED_RATE)
             .next()
         {
-            let mut samples = samples.clone();
-            let duration = std::time::Duration::from_secs_f64(
-                0.1 + samples.len() as f64 / REQUIRED_RATE.0 as f64,
-            );
-            println!("Going with f32");
-            let config = supported_config_range.with_max_sample_rate();
-            println!("\n\nsample rate is {:?}\n\n", config.sample_rate());
+            let config = supported_config_range.with_sample_rate(REQUIRED_RATE);
+            let channels = config.channels() as usize;
             let stream = device
                 .build_output_stream(
                     &config.into(),
                     move |data: &mut [f32], _| {
-                        for chunk in data.chunks_mut(2) {
+                        for frame in data.chunks_mut(channels) {
                             let value: f32 = Sample::from(&samples.pop().unwrap_or_default());
-                            for v in chunk.iter_mut() {
+                            for v in frame.iter_mut() {
                                 *v = value;
                             }
                         }
                     },
-                    |_| println!("had error"),
+                    |e| eprintln!("Error playing audio: {e}"),
                 )
                 .context("build_output_stream")?;
             stream.play().context("Playing stream")?;
@@ -229,192 +103,241 @@ fn send_audio_output_16kHz(mut samples: Vec<i16>) -> anyhow::Result<()> {
             return Ok(());
         }
     }
-    println!("No device supports i16 sampling");
-    Err(anyhow::anyhow!("No device suppports i16 output"))
+    Err(anyhow::anyhow!("No output device plays 16 kHz audio"))
 }
 
-pub fn voice_control(commands: impl 'static + Fn() -> Parser<Action>) {
-    let mut recognize_commands = load_voice_control(commands);
+/// How [`voice_control_with_source`] listens and decides what it heard.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub segmentation: Segmentation,
+    pub choice: ChoiceConfig,
+    /// Where we report what is going on.
+    pub events: Events,
+    /// Where to keep what we hear, if anywhere.
+    pub archive: Option<ArchiveConfig>,
+}
 
-    let vad = std::sync::Mutex::new(webrtc_vad::Vad::new_with_rate_and_mode(
-        webrtc_vad::SampleRate::Rate16kHz,
-        webrtc_vad::VadMode::VeryAggressive,
-    ));
+/// Listen to the default microphone and run whatever `commands` we hear,
+/// logging what happens to stdout.
+#[cfg(feature = "coqui")]
+pub fn voice_control(commands: impl 'static + Fn() -> Parser<Action>) -> anyhow::Result<()> {
+    let config = recognizer::RecognizerConfig::find()?;
+    let options = Options {
+        events: Events::stdout(events::LogFormat::Human),
+        ..Options::default()
+    };
+    voice_control_with_source(
+        audio::Microphone::new()?,
+        &options,
+        move || recognizer::Coqui::load(&config),
+        commands,
+    )
+}
 
-    let mut have_sound = false;
-    let mut silence_check: Vec<i16> = Vec::new();
-    let mut all_data: Vec<i16> = Vec::new();
+/// Run whatever `commands` are heard in `source`, returning once the source
+/// is exhausted.  The speech recognizer is created by `load`.
+pub fn voice_control_with_source<R: SpeechRecognizer>(
+    mut source: impl AudioSource,
+    options: &Options,
+    load: impl 'static + Send + FnOnce() -> anyhow::Result<R>,
+    commands: impl 'static + Fn() -> Parser<Action>,
+) -> anyhow::Result<()> {
+    let events = &options.events;
+    let listening = Listening::with_default_phrases(commands());
+    let mut recognizer = StreamingRecognizer::new(load, listening.to_checker(), events)?;
+    let mut segmenter = options.segmentation.segmenter(events);
+    let mut archive = options.archive.as_ref().map(Archive::open).transpose()?;
 
-    let mut audio_sample = 0;
     let mut total_seconds = 0.0;
-    let mut last_printed = 0.0;
-
-    println!("trying to get audio input...");
-    get_audio_input_16kHz(move |data: &[i16]| {
-        let frame = data.len() as f64 * (1.0 / REQUIRED_RATE.0 as f64);
-        total_seconds += frame;
-        if total_seconds > last_printed + 10.0 {
-            println!("It has been {total_seconds:.1} seconds in frames of {frame} seconds");
-            last_printed = total_seconds;
+    let mut last_reported = 0.0;
+
+    let mut handle_phrase = |utterance: Utterance, transcripts: Vec<Transcript>, timing: Timing| {
+        let was_asleep = listening.state() == ListeningState::Asleep;
+        let recognition =
+            Recognition::new(transcripts, listening.grammar(), &options.choice, timing);
+        report_recognition(events, &recognition);
+        if let Some(heard) = &recognition.action {
+            listening.follow(heard);
         }
-        silence_check.extend(data);
-        if silence_check.len() < SILENCE_BETWEEN_PHRASES {
-            return;
+        let action = match &recognition.action {
+            Some(Heard::Command(action)) => {
+                let name = format!("{action:?}");
+                events.emit(Event::ActionExecuted {
+                    action: name.clone(),
+                });
+                action.run();
+                Some(name)
+            }
+            Some(Heard::Sleep) => {
+                events.emit(Event::Asleep);
+                Some("sleep".to_string())
+            }
+            Some(Heard::Wake) => {
+                events.emit(Event::Awake);
+                Some("wake".to_string())
+            }
+            // What we overhear while asleep is none of our business.
+            None if was_asleep => return,
+            None => None,
+        };
+        let archive = match &mut archive {
+            Some(archive) => archive,
+            None => return,
+        };
+        let name = recognition
+            .chosen()
+            .map_or("unrecognized", |h| h.text.as_str())
+            .to_string();
+        let metadata = ClipMetadata::new(utterance.start, utterance.end, &recognition, action);
+        match archive.save(&utterance.samples, &name, &metadata) {
+            Ok((clip, pruned)) => {
+                events.emit(Event::Saved {
+                    path: clip.wav,
+                    samples: utterance.samples.len(),
+                });
+                for clip in pruned {
+                    events.emit(Event::Pruned { path: clip.wav });
+                }
+            }
+            Err(e) => events.error(&e.context("Unable to archive what we heard")),
         }
-        let mut vad = vad.lock().unwrap();
-        if silence_check
-            .chunks_exact(VAD_SAMPLES as usize)
-            .any(|data| vad.is_voice_segment(data).expect("wrong size data sample"))
-        {
-            all_data.extend(&silence_check);
-            // println!("Found audio {} samples", all_data.len());
-            have_sound = true;
-        } else {
-            if have_sound {
-                // Include the final silence, which might include the very end of a consonant.
-                all_data.extend(&silence_check);
-                // let fname = format!("audio/final-silence-{audio_sample:06}.wav");
-                // println!("Final silence {} samples as {fname}", silence_check.len());
-                // println!("final silence is {silence_check:?}");
-                // save_data(fname.as_str(), &silence_check);
-                // audio_sample += 1;
-                let fname = if let Some(action) = recognize_commands(&all_data) {
-                    action.run();
-                    format!("audio/{audio_sample:06}-run-{action:?}.wav")
-                } else {
-                    format!("audio/{audio_sample:06}-unrecognized.wav")
-                };
-                println!("Saving {} samples as {fname}", all_data.len());
-                save_data(fname.as_str(), &all_data);
-                audio_sample += 1;
-            } else {
-                // let fname = format!("audio/silence-{audio_sample:06}.wav");
-                // println!("Discarding {} samples as {fname}", silence_check.len());
-                // save_data(fname.as_str(), &silence_check);
-                // audio_sample += 1;
+    };
+    let mut handle_event = |event: SegmentEvent, recognizer: &mut StreamingRecognizer| match event {
+        SegmentEvent::Started { start } => events.emit(Event::UtteranceStarted {
+            start: start.as_secs_f64(),
+        }),
+        SegmentEvent::Ended(utterance) => {
+            events.emit(Event::UtteranceEnded {
+                start: utterance.start.as_secs_f64(),
+                end: utterance.end.as_secs_f64(),
+            });
+            let start = Instant::now();
+            let transcripts = recognizer.finish(&utterance.samples);
+            let timing = Timing {
+                audio: utterance.end - utterance.start,
+                recognizing: start.elapsed(),
+            };
+            handle_phrase(utterance, transcripts, timing)
+        }
+        SegmentEvent::Abandoned { start, end } => {
+            recognizer.cancel();
+            events.emit(Event::UtteranceAbandoned {
+                start: start.as_secs_f64(),
+                end: end.as_secs_f64(),
+            });
+        }
+    };
+    audio::for_each_chunk(&mut source, events, |chunk| {
+        let data = match chunk {
+            Chunk::Audio(data) => data,
+            // Don't glue what we heard before we lost the audio onto what
+            // we hear now.
+            Chunk::Reconnected => {
+                segmenter.reset();
+                recognizer.cancel();
+                return;
             }
-            have_sound = false;
-            all_data.clear();
+        };
+        total_seconds += data.len() as f64 * (1.0 / REQUIRED_RATE.0 as f64);
+        if total_seconds > last_reported + 10.0 {
+            events.emit(Event::AudioProgress {
+                seconds: total_seconds,
+            });
+            last_reported = total_seconds;
         }
-        silence_check.clear();
-    });
+        for event in segmenter.push(data) {
+            handle_event(event, &mut recognizer);
+        }
+        // Keep the recognizer busy while the speaker is still talking.
+        if let Some(so_far) = segmenter.current() {
+            recognizer.update(so_far);
+            for text in recognizer.partial_results() {
+                events.emit(Event::Partial { text });
+            }
+        }
+    })?;
+    // Make sure we finish up any phrase that was still in progress.
+    if let Some(event) = segmenter.flush() {
+        handle_event(event, &mut recognizer);
+    }
+    Ok(())
 }
 
 const LISTEN_TO_INPUT: bool = false;
 
+/// Steer `recognizer` towards `commands`, returning a function that works
+/// out which command, if any, was spoken in a phrase.
 pub fn load_voice_control(
+    mut recognizer: impl 'static + SpeechRecognizer,
+    config: &ChoiceConfig,
     commands: impl Fn() -> Parser<Action>,
-) -> impl 'static + FnMut(&[i16]) -> Option<Action> {
-    let mut model = coqui_stt::Model::new("english/model.tflite").expect("unable to create model");
-    model
-        .enable_external_scorer("english/huge-vocabulary.scorer")
-        .expect("unable to read scorer");
-    assert_eq!(model.get_sample_rate(), REQUIRED_RATE.0 as i32);
-    let model_commands = commands();
-    let checker = model_commands.to_checker();
-    let checker_two = model_commands.to_checker();
-    model
-        .enable_callback_scorer(move |s| {
-            let v = if let Err(Error::Wrong) = checker(s) {
-                // println!("      bad input {:?}", s);
-                -10.0
-            } else {
-                // println!("      good input {:?}", s);
-                0.0
-            };
-            // println!("score {v:4}: {s:?}");
-            v
-        })
-        .expect("unable to apply callback scorer");
-
-    let execute_commands = commands();
-
-    move |data: &[i16]| -> Option<Action> {
+) -> anyhow::Result<impl 'static + FnMut(&[i16]) -> anyhow::Result<Recognition<Action>>> {
+    let checker: Checker = Arc::new(commands().to_checker());
+    recognizer.set_checker(checker)?;
+    let execute_commands = commands().compile();
+    let config = config.clone();
+    Ok(move |data: &[i16]| -> anyhow::Result<Recognition<Action>> {
         if LISTEN_TO_INPUT {
             send_audio_output_16kHz(data.to_vec()).ok();
         }
-        const NUM_GUESSES: u32 = 16;
-        let x = model
-            .speech_to_text_with_metadata(data, NUM_GUESSES)
-            .unwrap()
-            .to_owned();
-        let transcripts = x.transcripts();
-        let scores: Vec<f64> = transcripts.iter().map(|c| c.confidence()).collect();
-        let phrases: Vec<String> = transcripts
-            .iter()
-            .map(|c| {
-                let mut words = String::new();
-                for w in c.tokens().iter().map(|t| &t.text) {
-                    words.push_str(w.as_ref());
-                }
-                // Remove trailing space.
-                while words.len() > 0 && words.as_bytes()[words.len() - 1] == b' ' {
-                    words.pop();
-                }
-                words
-            })
-            .collect();
-        if phrases.len() == 1 {
-            if phrases[0] == "" {
-                println!("You didn't say anything")
-            }
-        } else {
-            println!(
-                "{:?} exceeds {:?} by {:?}",
-                phrases[0],
-                phrases[1],
-                scores[0] - scores[1]
-            );
-        }
-        for (score, phrase) in scores.iter().copied().zip(phrases.iter()) {
-            println!(
-                "   {} {score:.2}: {phrase:?}",
-                if checker_two(&*phrase).is_ok() {
-                    "OK"
-                } else {
-                    "  "
-                }
-            );
-        }
+        let start = Instant::now();
+        let transcripts = recognizer.recognize(data)?;
+        let timing = Timing {
+            audio: vad::samples_to_duration(data.len()),
+            recognizing: start.elapsed(),
+        };
+        Ok(Recognition::new(
+            transcripts,
+            &execute_commands,
+            &config,
+            timing,
+        ))
+    })
+}
 
-        if phrases[0] != "" {
-            match execute_commands.parse(&phrases[0]) {
-                Err(Error::Incomplete) => {
-                    // println!("    Maybe you didn't finish?");
-                    None
-                }
-                Err(Error::Wrong) => {
-                    // println!("    This is bogus!");
-                    None
-                }
-                Ok((action, "")) => {
-                    // println!("    Running action {action:?}");
-                    Some(action)
-                }
-                Ok((_action, _remainder)) => {
-                    // println!("    We had extra words: {remainder:?} after {action:?}");
-                    None
-                }
-            }
-        } else {
-            None
-        }
+/// Tell `events` about the recognizer's guesses, and which of them we went
+/// with.
+pub fn report_recognition<T>(events: &Events, recognition: &Recognition<T>) {
+    events.emit(Event::Hypotheses {
+        hypotheses: recognition.hypotheses.clone(),
+        audio: recognition.timing.audio.as_secs_f64(),
+        recognizing: recognition.timing.recognizing.as_secs_f64(),
+    });
+    match recognition.chosen() {
+        Some(chosen) => events.emit(Event::Chosen {
+            text: chosen.text.clone(),
+            confidence: chosen.confidence,
+            ambiguous: recognition.ambiguous,
+        }),
+        None => events.emit(Event::Unrecognized {
+            ambiguous: recognition.ambiguous,
+        }),
     }
 }
 
-fn save_data(fname: &str, data: &[i16]) {
+fn save_data(fname: impl AsRef<std::path::Path>, data: &[i16]) -> anyhow::Result<()> {
     let spec = hound::WavSpec {
         channels: 1,
         sample_rate: REQUIRED_RATE.0,
         bits_per_sample: 16,
         sample_format: hound::SampleFormat::Int,
     };
-    let mut writer = hound::WavWriter::create(fname, spec).unwrap();
+    let mut writer = hound::WavWriter::create(fname, spec)?;
     let mut writer = writer.get_i16_writer(data.len() as u32);
     for s in data.iter().copied() {
         writer.write_sample(s);
     }
-    writer.flush().ok();
+    writer.flush()?;
+    Ok(())
+}
+
+/// The 64-bit FNV-1a hash of `bytes`.  Unlike the standard library's
+/// hashers, this is the same on every platform and every version of Rust,
+/// so it is safe to store.
+pub(crate) fn fnv1a(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    bytes.into_iter().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
 }
 
 /// Only intended for testing/benchmarking
@@ -424,19 +347,28 @@ pub fn load_data(fname: &str) -> Vec<i16> {
     reader.into_samples().map(|s| s.unwrap()).collect()
 }
 
+#[test]
+fn stable_hash() {
+    assert_eq!(0xcbf2_9ce4_8422_2325, fnv1a([]));
+    assert_eq!(0xaf63_dc4c_8601_ec8c, fnv1a(*b"a"));
+}
+
 #[test]
 fn save_load() {
     let data = (1..1000).collect::<Vec<_>>();
     let tf = tempfile::NamedTempFile::new().unwrap().into_temp_path();
-    save_data(tf.to_str().unwrap(), &data);
+    save_data(tf.to_str().unwrap(), &data).unwrap();
     let new_data = load_data(tf.to_str().unwrap());
     assert_eq!(data, new_data);
 }
 
 #[test]
+#[cfg(feature = "coqui")]
 fn recognize_testing_testing_testing() {
     use parser::IntoParser;
 
+    let config = recognizer::RecognizerConfig::find().unwrap();
+
     let parser = || {
         parser::choose(
             "command",
@@ -450,17 +382,22 @@ fn recognize_testing_testing_testing() {
             ],
         )
     };
-    let mut recognizer = load_voice_control(parser);
+    let mut recognizer = load_voice_control(
+        recognizer::Coqui::load(&config).unwrap(),
+        &ChoiceConfig::default(),
+        parser,
+    )
+    .unwrap();
 
     // let sound = load_data("test-audio/testing.wav");
-    // let result = recognizer(&sound);
+    // let result = recognizer(&sound).unwrap().action;
     // println!("Result is {result:?}");
     // assert!(result.is_some());
     // let result = result.unwrap();
     // assert_eq!(format!("{result:?}"), r#""testing""#.to_string());
 
     let sound = load_data("test-audio/testing-testing-testing-unrecognized.wav");
-    let result = recognizer(&sound);
+    let result = recognizer(&sound).unwrap().action;
     println!("Result is {result:?}");
     assert!(result.is_some());
     let result = result.unwrap();
@@ -470,7 +407,7 @@ fn recognize_testing_testing_testing() {
     );
 
     let sound = load_data("test-audio/testing-testing-testing.wav");
-    let result = recognizer(&sound);
+    let result = recognizer(&sound).unwrap().action;
     println!("Result is {result:?}");
     assert!(result.is_some());
     let result = result.unwrap();
@@ -481,15 +418,23 @@ fn recognize_testing_testing_testing() {
 }
 
 #[test]
+#[cfg(feature = "coqui")]
 fn recognize_testing() {
     use parser::IntoParser;
 
+    let config = recognizer::RecognizerConfig::find().unwrap();
+
     let parser = || {
         "testing".map(|_| Action::new("Testing!".to_string(), || println!("I am running a test!")))
     };
-    let mut recognizer = load_voice_control(parser);
+    let mut recognizer = load_voice_control(
+        recognizer::Coqui::load(&config).unwrap(),
+        &ChoiceConfig::default(),
+        parser,
+    )
+    .unwrap();
     let sound = load_data("test-audio/testing.wav");
-    let result = recognizer(&sound);
+    let result = recognizer(&sound).unwrap().action;
     println!("Result is {result:?}");
     assert!(result.is_some());
     assert_eq!(format!("{result:?}"), r#"Some("Testing!")"#.to_string());
@@ -507,15 +452,233 @@ fn recognize_testing() {
             ],
         )
     };
-    let mut recognizer = load_voice_control(parser);
+    let mut recognizer = load_voice_control(
+        recognizer::Coqui::load(&config).unwrap(),
+        &ChoiceConfig::default(),
+        parser,
+    )
+    .unwrap();
     let sound = load_data("test-audio/testing.wav");
-    let result = recognizer(&sound);
+    let result = recognizer(&sound).unwrap().action;
     println!("Result is {result:?}");
     assert!(result.is_some());
     assert_eq!(format!("{result:?}"), r#"Some("Testing!")"#.to_string());
 
-    let mut recognizer = load_voice_control(parser::roundy::parser);
+    let mut recognizer = load_voice_control(
+        recognizer::Coqui::load(&config).unwrap(),
+        &ChoiceConfig::default(),
+        parser::roundy::parser,
+    )
+    .unwrap();
     let sound = load_data("test-audio/one-up.wav");
     let e = expect_test::expect![[r#"Some("[\"↑\"]")"#]];
-    e.assert_eq(&format!("{:?}", recognizer(&sound)));
+    e.assert_eq(&format!("{:?}", recognizer(&sound).unwrap().action));
+}
+
+#[test]
+fn voice_control_from_wav_files() {
+    use parser::IntoParser;
+    use std::sync::{Arc, Mutex};
+
+    let heard = Arc::new(Mutex::new(Vec::new()));
+    let also_heard = heard.clone();
+    let parser = move || {
+        let heard = also_heard.clone();
+        "testing".many1().map(move |t| {
+            let heard = heard.clone();
+            let phrase = t.join(" ");
+            Action::new(phrase.clone(), move || {
+                heard.lock().unwrap().push(phrase.clone())
+            })
+        })
+    };
+    let load = || {
+        let mut fake = recognizer::FakeRecognizer::new();
+        for (file, text) in [
+            ("testing", "testing"),
+            ("testing-testing-testing", "testing testing testing"),
+        ] {
+            let wav = format!("test-audio/{file}.wav");
+            fake.script_wav(wav, vec![Transcript::new(text, -1.0)])?;
+        }
+        // The best guess isn't a command, but a less likely one is.
+        fake.script_wav(
+            "test-audio/one-up.wav",
+            vec![
+                Transcript::new("go to sleepy", -1.0),
+                Transcript::new("go to sleep", -3.0),
+            ],
+        )?;
+        Ok(fake)
+    };
+    let source = audio::WavFiles::new([
+        "test-audio/testing.wav".into(),
+        "test-audio/testing-testing-testing.wav".into(),
+        "test-audio/one-up.wav".into(),
+        // We are asleep, so this is ignored.
+        "test-audio/testing.wav".into(),
+    ]);
+    let archive = tempfile::tempdir().unwrap();
+    let options = Options {
+        archive: Some(ArchiveConfig::new(archive.path())),
+        ..Options::default()
+    };
+    let chosen = Arc::new(Mutex::new(Vec::new()));
+    let c = chosen.clone();
+    options.events.subscribe(move |e: &Event| match e {
+        Event::Chosen { text, .. } => c.lock().unwrap().push(text.clone()),
+        Event::Asleep => c.lock().unwrap().push("(asleep)".to_string()),
+        _ => (),
+    });
+    voice_control_with_source(source, &options, load, parser).unwrap();
+    assert_eq!(
+        *heard.lock().unwrap(),
+        vec!["testing".to_string(), "testing testing testing".to_string()]
+    );
+    assert_eq!(
+        *chosen.lock().unwrap(),
+        [
+            "testing",
+            "testing testing testing",
+            "go to sleep",
+            "(asleep)"
+        ]
+    );
+    // What we heard while asleep isn't kept.
+    let clips: Vec<_> = archive::list_clips(archive.path())
+        .unwrap()
+        .into_iter()
+        .map(|c| c.wav.file_name().unwrap().to_string_lossy().into_owned())
+        .collect();
+    assert_eq!(
+        clips,
+        [
+            "000000-testing.wav",
+            "000001-testing-testing-testing.wav",
+            "000002-go-to-sleep.wav"
+        ]
+    );
+}
+
+#[test]
+fn reconnecting_in_the_middle_of_a_phrase() {
+    use parser::IntoParser;
+    use std::sync::{Arc, Mutex};
+
+    /// Loses the audio in the middle of saying "testing".
+    struct Unplugged {
+        chunks: Vec<anyhow::Result<Option<Vec<i16>>>>,
+    }
+    impl AudioSource for Unplugged {
+        fn next_chunk(&mut self, _: &Events) -> anyhow::Result<Option<Vec<i16>>> {
+            if self.chunks.is_empty() {
+                Ok(None)
+            } else {
+                self.chunks.remove(0)
+            }
+        }
+        fn reconnect(&mut self, _: &Events) -> anyhow::Result<()> {
+            Ok(())
+        }
+    }
+    let testing = load_data("test-audio/testing.wav");
+    // The word is loudest here.
+    let (before, after) = testing.split_at(2400);
+    let source = Unplugged {
+        chunks: vec![
+            Ok(Some(before.to_vec())),
+            Err(anyhow::anyhow!("unplugged")),
+            Ok(Some(after.to_vec())),
+            Ok(Some(vec![0; RATE_AS_USIZE])),
+        ],
+    };
+    // Only what we hear after reconnecting makes sense on its own.
+    let after = after.to_vec();
+    let load = move || {
+        let mut fake = recognizer::FakeRecognizer::new();
+        fake.script(&after, vec![Transcript::new("testing", -1.0)]);
+        Ok(fake)
+    };
+    let heard = Arc::new(Mutex::new(Vec::new()));
+    let also_heard = heard.clone();
+    let parser = move || {
+        let heard = also_heard.clone();
+        "testing".map(move |_| {
+            let heard = heard.clone();
+            Action::new("testing".to_string(), move || {
+                heard.lock().unwrap().push("testing")
+            })
+        })
+    };
+    let options = Options::default();
+    let utterances = Arc::new(Mutex::new(Vec::new()));
+    let u = utterances.clone();
+    options.events.subscribe(move |e: &Event| {
+        if let Event::UtteranceEnded { start, .. } = e {
+            u.lock().unwrap().push(*start)
+        }
+    });
+    voice_control_with_source(source, &options, load, parser).unwrap();
+    assert_eq!(*heard.lock().unwrap(), ["testing"]);
+    // Nothing from before we lost the audio.
+    let reconnected = vad::samples_to_duration(before.len()).as_secs_f64();
+    assert!(
+        utterances
+            .lock()
+            .unwrap()
+            .iter()
+            .all(|&start| start >= reconnected),
+        "{utterances:?}"
+    );
+}
+
+#[test]
+fn clicks_dont_spoil_the_next_phrase() {
+    use parser::IntoParser;
+    use std::sync::{Arc, Mutex};
+
+    let testing = load_data("test-audio/testing.wav");
+    // Just long enough to start an utterance, but too short to keep.
+    let dir = tempfile::tempdir().unwrap();
+    let click = dir.path().join("click.wav");
+    let mut samples = vec![0; RATE_AS_USIZE / 2];
+    samples.extend_from_slice(&testing[1000..2000]);
+    save_data(&click, &samples).unwrap();
+    let source = audio::WavFiles::new([click, "test-audio/testing.wav".into()]);
+    let load = || {
+        let mut fake = recognizer::FakeRecognizer::new();
+        fake.script_wav(
+            "test-audio/testing.wav",
+            vec![Transcript::new("testing", -1.0)],
+        )?;
+        Ok(fake)
+    };
+    let heard = Arc::new(Mutex::new(Vec::new()));
+    let also_heard = heard.clone();
+    let parser = move || {
+        let heard = also_heard.clone();
+        "testing".map(move |_| {
+            let heard = heard.clone();
+            Action::new("testing".to_string(), move || {
+                heard.lock().unwrap().push("testing")
+            })
+        })
+    };
+    let options = Options {
+        segmentation: Segmentation::Vad(vad::VadConfig {
+            min_utterance: std::time::Duration::from_millis(90),
+            ..vad::VadConfig::default()
+        }),
+        ..Options::default()
+    };
+    let abandoned = Arc::new(Mutex::new(0));
+    let a = abandoned.clone();
+    options.events.subscribe(move |e: &Event| {
+        if let Event::UtteranceAbandoned { .. } = e {
+            *a.lock().unwrap() += 1;
+        }
+    });
+    voice_control_with_source(source, &options, load, parser).unwrap();
+    assert_eq!(*abandoned.lock().unwrap(), 1);
+    assert_eq!(*heard.lock().unwrap(), ["testing"]);
 }
@@ -0,0 +1,174 @@
+//! Going to sleep, so that conversation with colleagues doesn't trigger
+//! keystrokes, and waking up again.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::parser::{choose, CompiledParser, Error, IntoParser, IsParser, ParseError, Parser};
+
+/// Whether we act on what we hear.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListeningState {
+    /// Listening for commands, or being told to go to sleep.
+    Awake,
+    /// Only listening for being told to wake up.
+    Asleep,
+}
+
+/// What we made of a phrase.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Heard<T> {
+    Command(T),
+    Sleep,
+    Wake,
+}
+
+/// Commands, plus phrases that put us to sleep and wake us up again.
+///
+/// While asleep the only grammar is the wake phrase, so the recognizer can't
+/// even mistake chatter for a command.
+pub struct Listening<T> {
+    asleep: Arc<AtomicBool>,
+    awake_grammar: CompiledParser<Heard<T>>,
+    asleep_grammar: CompiledParser<Heard<T>>,
+}
+
+impl<T: 'static> Listening<T> {
+    pub fn new(commands: Parser<T>, sleep: Parser<()>, wake: Parser<()>) -> Self {
+        Listening {
+            asleep: Arc::new(AtomicBool::new(false)),
+            awake_grammar: choose(
+                "listening",
+                vec![sleep.map(|_| Heard::Sleep), commands.map(Heard::Command)],
+            )
+            .compile(),
+            asleep_grammar: wake.map(|_| Heard::Wake).compile(),
+        }
+    }
+
+    /// Listen for `commands`, going to sleep on "go to sleep" and waking on
+    /// "wake up".
+    pub fn with_default_phrases(commands: Parser<T>) -> Self {
+        Listening::new(commands, "go to sleep".map(|_| ()), "wake up".map(|_| ()))
+    }
+
+    pub fn state(&self) -> ListeningState {
+        if self.asleep.load(Ordering::Relaxed) {
+            ListeningState::Asleep
+        } else {
+            ListeningState::Awake
+        }
+    }
+
+    pub fn set_state(&self, state: ListeningState) {
+        self.asleep
+            .store(state == ListeningState::Asleep, Ordering::Relaxed)
+    }
+
+    /// The grammar for what we might hear right now.
+    pub fn grammar(&self) -> &CompiledParser<Heard<T>> {
+        match self.state() {
+            ListeningState::Awake => &self.awake_grammar,
+            ListeningState::Asleep => &self.asleep_grammar,
+        }
+    }
+
+    /// Compile a checker that follows our state, so that while we sleep it
+    /// only accepts the wake grammar.
+    pub fn to_checker(&self) -> impl 'static + Fn(&str) -> Result<(), Error> {
+        let asleep = self.asleep.clone();
+        let awake_checker = self.awake_grammar.parser().to_checker();
+        let asleep_checker = self.asleep_grammar.parser().to_checker();
+        move |s| {
+            if asleep.load(Ordering::Relaxed) {
+                asleep_checker(s)
+            } else {
+                awake_checker(s)
+            }
+        }
+    }
+
+    /// Compile a checker for anything we might hear, awake or asleep, that
+    /// says where a phrase goes wrong for the awake grammar.
+    pub fn to_full_diagnoser(&self) -> impl 'static + Fn(&str) -> Result<(), ParseError> {
+        let awake_diagnoser = self.awake_grammar.parser().to_diagnoser();
+        let asleep_checker = self.asleep_grammar.parser().to_checker();
+        move |s| awake_diagnoser(s).or_else(|e| asleep_checker(s).map_err(|_| e))
+    }
+
+    /// Make sense of a whole phrase, without acting on it.
+    pub fn parse(&self, phrase: &str) -> Option<Heard<T>> {
+        match self.grammar().parse(phrase) {
+            Ok((heard, "")) => Some(heard),
+            _ => None,
+        }
+    }
+
+    /// Go to sleep or wake up if `heard` told us to.
+    pub fn follow(&self, heard: &Heard<T>) {
+        match heard {
+            Heard::Sleep => self.set_state(ListeningState::Asleep),
+            Heard::Wake => self.set_state(ListeningState::Awake),
+            Heard::Command(_) => (),
+        }
+    }
+
+    /// Make sense of a whole phrase, going to sleep or waking up if we were
+    /// told to.
+    pub fn hear(&self, phrase: &str) -> Option<Heard<T>> {
+        let heard = self.parse(phrase)?;
+        self.follow(&heard);
+        Some(heard)
+    }
+}
+
+#[cfg(test)]
+fn numbers() -> Parser<&'static str> {
+    choose("number", vec!["one", "two", "three"])
+}
+
+#[test]
+fn sleep_and_wake() {
+    let listening = Listening::with_default_phrases(numbers());
+    assert_eq!(ListeningState::Awake, listening.state());
+    assert_eq!(Some(Heard::Command("two")), listening.hear("two"));
+    assert_eq!(None, listening.hear("two three"));
+    // Parsing alone doesn't send us to sleep.
+    assert_eq!(Some(Heard::Sleep), listening.parse("go to sleep"));
+    assert_eq!(ListeningState::Awake, listening.state());
+
+    assert_eq!(Some(Heard::Sleep), listening.hear("go to sleep"));
+    assert_eq!(ListeningState::Asleep, listening.state());
+    // Commands are ignored while we sleep.
+    assert_eq!(None, listening.hear("two"));
+    assert_eq!(None, listening.hear("go to sleep"));
+    assert_eq!(ListeningState::Asleep, listening.state());
+
+    assert_eq!(Some(Heard::Wake), listening.hear("wake up"));
+    assert_eq!(ListeningState::Awake, listening.state());
+    assert_eq!(Some(Heard::Command("three")), listening.hear("three"));
+    // Waking up when we are already awake isn't a thing.
+    assert_eq!(None, listening.hear("wake up"));
+}
+
+#[test]
+fn checker_follows_state() {
+    let listening = Listening::with_default_phrases(numbers());
+    let check = listening.to_checker();
+    assert!(check("one").is_ok());
+    assert!(check("go to sleep").is_ok());
+    assert_eq!(Err(Error::Wrong), check("wake up"));
+
+    listening.set_state(ListeningState::Asleep);
+    assert_eq!(Err(Error::Wrong), check("one"));
+    assert_eq!(Err(Error::Wrong), check("go to sleep"));
+    assert_eq!(Err(Error::Incomplete), check("wake"));
+    assert!(check("wake up").is_ok());
+
+    let check = listening.to_full_diagnoser();
+    assert!(check("one").is_ok());
+    assert!(check("wake up").is_ok());
+    let e = check("go to").unwrap_err();
+    assert_eq!(Error::Incomplete, e.error);
+    assert_eq!("after 'go to' expected sleep", e.to_string());
+}
@@ -1,11 +1,16 @@
 use std::{collections::HashMap, sync::Arc};
 
+pub mod generate;
 pub mod number;
 pub mod roundy;
 pub mod spelling;
 
+mod ambiguity;
+mod compiled;
 mod regular;
-pub use regular::{State, DFA};
+pub use ambiguity::{ambiguities, assert_unambiguous, Ambiguity};
+pub use compiled::CompiledParser;
+pub use regular::{Completions, State, DFA};
 
 use self::regular::RegularGrammar;
 
@@ -15,23 +20,178 @@ pub enum Error {
     Wrong,
 }
 
+/// Where a phrase went wrong, and what would have been fine there.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ParseError {
+    pub error: Error,
+    /// How many words were fine before it went wrong.
+    pub offset: usize,
+    /// Those words.
+    pub after: String,
+    /// The words, or names of choices, that could have come next.
+    pub expected: Vec<String>,
+}
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.offset > 0 {
+            write!(f, "after '{}' ", self.after)?;
+        }
+        match self.expected.as_slice() {
+            [] => f.write_str("expected nothing more"),
+            [word] => write!(f, "expected {word}"),
+            words => write!(f, "expected one of: {}", words.join(", ")),
+        }
+    }
+}
+
+/// Where a parser could finish with an input, as the number of bytes of
+/// input it would leave over, most preferred first.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Ends {
+    pub leftovers: Vec<usize>,
+    /// Whether the input could be the start of something longer.
+    pub incomplete: bool,
+}
+impl Ends {
+    fn from_result<T>(result: Result<(T, &str), Error>) -> Self {
+        match result {
+            Ok((_, rest)) => Ends {
+                leftovers: vec![rest.len()],
+                incomplete: false,
+            },
+            Err(e) => Ends {
+                leftovers: Vec::new(),
+                incomplete: e == Error::Incomplete,
+            },
+        }
+    }
+    fn contains(&self, leftover: usize) -> bool {
+        self.leftovers.contains(&leftover)
+    }
+    fn push(&mut self, leftover: usize) {
+        if !self.contains(leftover) {
+            self.leftovers.push(leftover);
+        }
+    }
+    fn extend(&mut self, other: Ends) {
+        for leftover in other.leftovers {
+            self.push(leftover);
+        }
+        self.incomplete |= other.incomplete;
+    }
+}
+
+/// What we have found out about where parsers end while parsing one input,
+/// so that backtracking doesn't do the same work over and over.  Inputs are
+/// all ends of the same string, so we know them by their length.
+#[derive(Default)]
 pub struct Packrat {
-    failures: Vec<HashMap<String, Error>>,
+    /// By the address of the parser and the length of the input.
+    ends: HashMap<(usize, usize), Ends>,
+    /// Where any number of repeats of the parser could end.
+    repeats: HashMap<(usize, usize), Ends>,
 }
-impl Packrat {
-    fn check(&self, name: &str, input: &str) -> Result<(), Error> {
-        if self.failures.len() > input.len() {
-            if let Some(e) = self.failures[input.len()].get(name) {
-                return Err(e.clone());
-            }
+
+/// The choices [`IsParser::search`] made on its way through an input, so
+/// that [`IsParser::replay`] can build the output without searching again.
+pub struct Trace {
+    /// Which option of each choice, whether each optional part is there,
+    /// and before each repeat whether there is another.
+    choices: Vec<usize>,
+    /// How many more words we will try to match before giving up.
+    steps: usize,
+    /// Whether any of the words we tried could have been finished later.
+    incomplete: bool,
+}
+impl Trace {
+    fn new(input: &str) -> Self {
+        Trace {
+            choices: Vec::new(),
+            // Enough for any sensible grammar, but a search through a
+            // badly ambiguous one can take exponentially many.
+            steps: 64 * (input.len() + 16),
+            incomplete: false,
         }
-        Ok(())
     }
-    fn report(&mut self, name: &str, input: &str, e: Error) {
-        if self.failures.len() > input.len() {
-            self.failures[input.len()].insert(name.to_string(), e);
+
+    fn step(&mut self) -> bool {
+        self.steps = self.steps.saturating_sub(1);
+        !self.gave_up()
+    }
+
+    fn gave_up(&self) -> bool {
+        self.steps == 0
+    }
+
+    /// Try `f` having made `choice`, forgetting it if that went nowhere.
+    fn choose(&mut self, choice: usize, f: impl FnOnce(&mut Self) -> bool) -> bool {
+        self.choices.push(choice);
+        if f(self) {
+            return true;
+        }
+        self.choices.pop();
+        false
+    }
+}
+
+/// Take the next choice recorded in a [`Trace`].
+fn next_choice(choices: &mut &[usize]) -> Option<usize> {
+    let (&choice, rest) = choices.split_first()?;
+    *choices = rest;
+    Some(choice)
+}
+
+/// The end of `input` that is `leftover` bytes long.
+fn rest_of(input: &str, leftover: usize) -> &str {
+    &input[input.len() - leftover..]
+}
+
+/// Parse all of `input` if we can, and otherwise as much as the parser
+/// prefers.  Alternatives are explored fully, so this accepts the whole of
+/// `input` exactly when the parser's [`DFA`] does.
+fn parse_ends<'a, P: IsParser + ?Sized>(
+    parser: &P,
+    input: &'a str,
+) -> Result<(P::Output, &'a str), Error> {
+    // Try the ways through one at a time, until one takes all of the input.
+    // Failing that, the first way through any of it is where the parser
+    // would rather finish.
+    let mut trace = Trace::new(input);
+    let mut finishes = false;
+    let whole = parser.search(input, &mut trace, &mut |rest, _| {
+        finishes = true;
+        rest.is_empty()
+    });
+    let found = whole || (finishes && parser.search(input, &mut trace, &mut |_, _| true));
+    if !trace.gave_up() {
+        if found {
+            return parser
+                .replay(input, &mut trace.choices.as_slice())
+                .ok_or(Error::Wrong);
         }
+        return Err(if trace.incomplete {
+            Error::Incomplete
+        } else {
+            Error::Wrong
+        });
     }
+    // The grammar is so ambiguous that we had better remember where each
+    // part can finish rather than trying every way through it.
+    let packrat = &mut Packrat::default();
+    let ends = parser.ends(input, packrat);
+    let leftover = if ends.contains(0) {
+        0
+    } else {
+        match ends.leftovers.first() {
+            Some(leftover) => *leftover,
+            None if ends.incomplete => return Err(Error::Incomplete),
+            None => return Err(Error::Wrong),
+        }
+    };
+    let value = parser
+        .parse_exactly(input, leftover, packrat)
+        .ok_or(Error::Wrong)?;
+    Ok((value, rest_of(input, leftover)))
 }
 
 #[derive(Debug)]
@@ -39,6 +199,65 @@ pub struct Description {
     command: String,
     patterns: Vec<(String, Vec<String>)>,
 }
+impl Description {
+    /// Replace `words` that make up all the first words of a choice with
+    /// its name, in the order they come up in the description.
+    fn group_words(&self, words: &[String]) -> Vec<String> {
+        let mut choices: Vec<(&str, Vec<&str>)> = Vec::new();
+        for (name, options) in self.patterns.iter() {
+            // Only choices between plain words, so we don't name a choice
+            // that itself holds choices.
+            if !options
+                .iter()
+                .all(|o| o.chars().all(|c| c.is_ascii_lowercase() || c == ' '))
+            {
+                continue;
+            }
+            let mut first_words: Vec<&str> =
+                options.iter().filter_map(|o| o.split(' ').next()).collect();
+            first_words.sort_unstable();
+            first_words.dedup();
+            if first_words.len() > 1 && first_words.iter().all(|w| words.iter().any(|x| x == w)) {
+                choices.push((name, first_words));
+            }
+        }
+        // Bigger choices win over the ones they include.
+        choices.sort_by_key(|(_, first_words)| std::cmp::Reverse(first_words.len()));
+        let mut grouped: Vec<&str> = Vec::new();
+        let mut covered: Vec<&str> = Vec::new();
+        for (name, first_words) in choices {
+            if !first_words.iter().all(|w| covered.contains(w)) {
+                grouped.push(name);
+                covered.extend(first_words);
+            }
+        }
+
+        let mut out: Vec<String> = Vec::new();
+        let mut add = |item: &str| {
+            if !out.iter().any(|x| x == item) {
+                out.push(item.to_string());
+            }
+        };
+        let options = self.patterns.iter().flat_map(|(_, options)| options.iter());
+        for token in std::iter::once(&self.command)
+            .chain(options)
+            .flat_map(|o| o.split(' '))
+            .map(|t| t.trim_matches(|c| "()+*?|".contains(c)))
+        {
+            if grouped.contains(&token)
+                || (words.iter().any(|w| w == token) && !covered.contains(&token))
+            {
+                add(token);
+            }
+        }
+        for word in words.iter() {
+            if !covered.contains(&word.as_str()) {
+                add(word);
+            }
+        }
+        out
+    }
+}
 impl std::fmt::Display for Description {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         use std::fmt::Write;
@@ -75,11 +294,56 @@ pub trait IsParser: Sync + Send {
         }
     }
 
-    fn parse_with_packrat<'a>(
+    /// Everywhere the parser could finish with `input`, trying every
+    /// alternative.  By default this is wherever [`IsParser::parse`] stops.
+    fn ends(&self, input: &str, _packrat: &mut Packrat) -> Ends {
+        Ends::from_result(self.parse(input))
+    }
+
+    /// Parse `input`, leaving exactly `leftover` bytes of it.  This should
+    /// succeed for every leftover in [`IsParser::ends`].
+    fn parse_exactly(
+        &self,
+        input: &str,
+        leftover: usize,
+        _packrat: &mut Packrat,
+    ) -> Option<Self::Output> {
+        match self.parse(input) {
+            Ok((v, rest)) if rest.len() == leftover => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Try the ways the parser could start `input`, most preferred first,
+    /// until `then` accepts what it leaves over, noting the choices made in
+    /// `trace`.  By default there is only the way [`IsParser::parse`] goes.
+    fn search(
+        &self,
+        input: &str,
+        trace: &mut Trace,
+        then: &mut dyn FnMut(&str, &mut Trace) -> bool,
+    ) -> bool {
+        if !trace.step() {
+            return false;
+        }
+        match self.parse(input) {
+            Ok((_, rest)) => then(rest, trace),
+            Err(e) => {
+                trace.incomplete |= e == Error::Incomplete;
+                false
+            }
+        }
+    }
+
+    /// Parse `input` the way [`IsParser::search`] did, taking its choices
+    /// from the front of `choices`.
+    fn replay<'a>(
         &self,
         input: &'a str,
-        packrat: &mut Packrat,
-    ) -> Result<(Self::Output, &'a str), Error>;
+        _choices: &mut &[usize],
+    ) -> Option<(Self::Output, &'a str)> {
+        self.parse(input).ok()
+    }
 
     fn describe(&self) -> Description;
 
@@ -87,7 +351,54 @@ pub trait IsParser: Sync + Send {
         false
     }
 
+    /// Add any options of `choose`s in the parser that can never be chosen
+    /// to `found`.
+    fn find_shadowed(&self, _found: &mut Vec<Ambiguity>) {}
+
     fn to_grammar(&self, next_position: &mut usize) -> RegularGrammar;
+
+    /// Number our positions the way [`IsParser::to_grammar`] does, noting
+    /// where each of our parts' positions are.
+    fn tag(&self, next_position: &mut usize) -> Tag {
+        let start = *next_position;
+        self.to_grammar(next_position);
+        Tag {
+            start,
+            end: *next_position,
+            parts: Vec::new(),
+        }
+    }
+
+    /// Build the output from the front of `path`, the position of the space
+    /// before each word of the input, given the `tag` for our positions.  By
+    /// default we don't know how, and leave it to [`IsParser::parse`].
+    fn parse_path(&self, _tag: &Tag, _path: &mut &[usize]) -> Option<Self::Output> {
+        None
+    }
+}
+
+/// Where the positions of a parser's grammar are, and those of its parts.
+#[derive(Debug)]
+pub struct Tag {
+    start: usize,
+    end: usize,
+    parts: Vec<Tag>,
+}
+impl Tag {
+    fn holds(&self, path: &[usize]) -> bool {
+        path.first()
+            .is_some_and(|p| (self.start..self.end).contains(p))
+    }
+
+    fn around(next_position: &mut usize, f: impl FnOnce(&mut usize) -> Vec<Tag>) -> Tag {
+        let start = *next_position;
+        let parts = f(next_position);
+        Tag {
+            start,
+            end: *next_position,
+            parts,
+        }
+    }
 }
 
 pub trait IntoParser: Sized + IsParser + 'static {
@@ -142,11 +453,9 @@ pub trait IntoParser: Sized + IsParser + 'static {
 }
 impl<PP: IsParser + 'static> IntoParser for PP {}
 
-#[derive(Clone)]
 pub struct Parser<T> {
     inner: P<T>,
 }
-#[derive(Clone)]
 enum P<T> {
     Raw(Arc<dyn IsParser<Output = T>>),
     Choose {
@@ -154,6 +463,19 @@ enum P<T> {
         options: Vec<Parser<T>>,
     },
 }
+// Parsers are shared rather than copied, so the output needn't be `Clone`.
+impl<T> Clone for Parser<T> {
+    fn clone(&self) -> Self {
+        let inner = match &self.inner {
+            P::Raw(p) => P::Raw(p.clone()),
+            P::Choose { name, options } => P::Choose {
+                name: name.clone(),
+                options: options.clone(),
+            },
+        };
+        Parser { inner }
+    }
+}
 
 struct Map<T, U> {
     parser: Parser<T>,
@@ -162,25 +484,49 @@ struct Map<T, U> {
 impl<T: 'static, U: 'static> IsParser for Map<T, U> {
     type Output = U;
     fn parse<'a>(&self, input: &'a str) -> Result<(U, &'a str), Error> {
+        parse_ends(self, input)
+    }
+
+    fn ends(&self, input: &str, packrat: &mut Packrat) -> Ends {
+        self.parser.ends(input, packrat)
+    }
+
+    fn parse_exactly(&self, input: &str, leftover: usize, packrat: &mut Packrat) -> Option<U> {
         self.parser
-            .parse(input)
-            .map(|(v, rest)| ((self.f)(v), rest))
+            .parse_exactly(input, leftover, packrat)
+            .map(&self.f)
     }
 
-    fn parse_with_packrat<'a>(
+    fn search(
         &self,
-        input: &'a str,
-        packrat: &mut Packrat,
-    ) -> Result<(Self::Output, &'a str), Error> {
-        self.parser
-            .parse_with_packrat(input, packrat)
-            .map(|(v, rest)| ((self.f)(v), rest))
+        input: &str,
+        trace: &mut Trace,
+        then: &mut dyn FnMut(&str, &mut Trace) -> bool,
+    ) -> bool {
+        self.parser.search(input, trace, then)
+    }
+
+    fn replay<'a>(&self, input: &'a str, choices: &mut &[usize]) -> Option<(U, &'a str)> {
+        let (value, rest) = self.parser.replay(input, choices)?;
+        Some(((self.f)(value), rest))
     }
 
     fn describe(&self) -> Description {
         self.parser.describe()
     }
 
+    fn find_shadowed(&self, found: &mut Vec<Ambiguity>) {
+        self.parser.find_shadowed(found)
+    }
+
+    fn tag(&self, next_position: &mut usize) -> Tag {
+        self.parser.tag(next_position)
+    }
+
+    fn parse_path(&self, tag: &Tag, path: &mut &[usize]) -> Option<U> {
+        self.parser.parse_path(tag, path).map(&self.f)
+    }
+
     fn to_grammar(&self, next_position: &mut usize) -> RegularGrammar {
         self.parser.to_grammar(next_position)
     }
@@ -193,19 +539,76 @@ struct Join<T, U, V> {
 impl<T: 'static, U: 'static, V: 'static> IsParser for Join<T, U, V> {
     type Output = V;
     fn parse<'a>(&self, input: &'a str) -> Result<(V, &'a str), Error> {
-        let (v1, input) = self.parser1.parse(input)?;
-        let (v2, rest) = self.parser2.parse(input)?;
-        Ok(((self.join)(v1, v2), rest))
+        parse_ends(self, input)
+    }
+
+    fn ends(&self, input: &str, packrat: &mut Packrat) -> Ends {
+        let first = self.parser1.ends(input, packrat);
+        let mut ends = Ends {
+            leftovers: Vec::new(),
+            incomplete: first.incomplete,
+        };
+        for middle in first.leftovers {
+            ends.extend(self.parser2.ends(rest_of(input, middle), packrat));
+        }
+        ends
     }
 
-    fn parse_with_packrat<'a>(
+    fn parse_exactly(&self, input: &str, leftover: usize, packrat: &mut Packrat) -> Option<V> {
+        // Backtrack into the first parser until the second can finish at
+        // the right place.
+        let middle = self
+            .parser1
+            .ends(input, packrat)
+            .leftovers
+            .into_iter()
+            .find(|&middle| {
+                self.parser2
+                    .ends(rest_of(input, middle), packrat)
+                    .contains(leftover)
+            })?;
+        let v1 = self.parser1.parse_exactly(input, middle, packrat)?;
+        let v2 = self
+            .parser2
+            .parse_exactly(rest_of(input, middle), leftover, packrat)?;
+        Some((self.join)(v1, v2))
+    }
+
+    fn search(
         &self,
-        input: &'a str,
-        packrat: &mut Packrat,
-    ) -> Result<(Self::Output, &'a str), Error> {
-        let (v1, input) = self.parser1.parse_with_packrat(input, packrat)?;
-        let (v2, rest) = self.parser2.parse_with_packrat(input, packrat)?;
-        Ok(((self.join)(v1, v2), rest))
+        input: &str,
+        trace: &mut Trace,
+        then: &mut dyn FnMut(&str, &mut Trace) -> bool,
+    ) -> bool {
+        self.parser1.search(input, trace, &mut |rest, trace| {
+            self.parser2.search(rest, trace, then)
+        })
+    }
+
+    fn replay<'a>(&self, input: &'a str, choices: &mut &[usize]) -> Option<(V, &'a str)> {
+        let (v1, rest) = self.parser1.replay(input, choices)?;
+        let (v2, rest) = self.parser2.replay(rest, choices)?;
+        Some(((self.join)(v1, v2), rest))
+    }
+
+    fn find_shadowed(&self, found: &mut Vec<Ambiguity>) {
+        self.parser1.find_shadowed(found);
+        self.parser2.find_shadowed(found);
+    }
+
+    fn tag(&self, next_position: &mut usize) -> Tag {
+        Tag::around(next_position, |next_position| {
+            vec![
+                self.parser1.tag(next_position),
+                self.parser2.tag(next_position),
+            ]
+        })
+    }
+
+    fn parse_path(&self, tag: &Tag, path: &mut &[usize]) -> Option<V> {
+        let v1 = self.parser1.parse_path(&tag.parts[0], path)?;
+        let v2 = self.parser2.parse_path(&tag.parts[1], path)?;
+        Some((self.join)(v1, v2))
     }
 
     fn describe(&self) -> Description {
@@ -251,34 +654,78 @@ impl<T: 'static> Parser<T> {
         let dfa = DFA::encode(self);
         move |s| dfa.check(s)
     }
+
+    /// Compile a checker that says where a `&str` goes wrong, and what it
+    /// expected there, naming the choices it could have taken.
+    pub fn to_diagnoser(&self) -> impl 'static + Fn(&str) -> Result<(), ParseError> {
+        let dfa = DFA::encode(self);
+        let description = self.describe();
+        move |s| {
+            dfa.diagnose(s).map_err(|mut e| {
+                e.expected = description.group_words(&e.expected);
+                e
+            })
+        }
+    }
 }
 
 impl<T: 'static> IsParser for Parser<T> {
     type Output = T;
     fn parse<'a>(&self, input: &'a str) -> Result<(T, &'a str), Error> {
-        let mut packrat = Packrat {
-            failures: vec![HashMap::new(); input.len()],
-        };
+        parse_ends(self, input)
+    }
+
+    fn ends(&self, input: &str, packrat: &mut Packrat) -> Ends {
         match &self.inner {
-            P::Raw(p) => p.parse_with_packrat(input, &mut packrat),
+            P::Raw(p) => p.ends(input, packrat),
             P::Choose { options, .. } => {
-                let mut e = Error::Wrong;
+                // Choices are where the work multiplies, so remember them.
+                let key = (self as *const Self as usize, input.len());
+                if let Some(ends) = packrat.ends.get(&key) {
+                    return ends.clone();
+                }
+                let mut ends = Ends::default();
                 for parser in options.iter() {
-                    match parser.parse_with_packrat(input, &mut packrat) {
-                        Ok(v) => {
-                            return Ok(v);
-                        }
-                        Err(Error::Incomplete) => {
-                            e = Error::Incomplete;
-                        }
-                        Err(Error::Wrong) => (),
-                    }
+                    ends.extend(parser.ends(input, packrat));
                 }
-                Err(e)
+                packrat.ends.insert(key, ends.clone());
+                ends
             }
         }
     }
 
+    fn parse_exactly(&self, input: &str, leftover: usize, packrat: &mut Packrat) -> Option<T> {
+        match &self.inner {
+            P::Raw(p) => p.parse_exactly(input, leftover, packrat),
+            P::Choose { options, .. } => options
+                .iter()
+                .find(|parser| parser.ends(input, packrat).contains(leftover))?
+                .parse_exactly(input, leftover, packrat),
+        }
+    }
+
+    fn search(
+        &self,
+        input: &str,
+        trace: &mut Trace,
+        then: &mut dyn FnMut(&str, &mut Trace) -> bool,
+    ) -> bool {
+        match &self.inner {
+            P::Raw(p) => p.search(input, trace, then),
+            P::Choose { options, .. } => options
+                .iter()
+                .enumerate()
+                .any(|(i, parser)| trace.choose(i, |trace| parser.search(input, trace, then))),
+        }
+    }
+
+    fn replay<'a>(&self, input: &'a str, choices: &mut &[usize]) -> Option<(T, &'a str)> {
+        match &self.inner {
+            P::Raw(p) => p.replay(input, choices),
+            P::Choose { options, .. } => options.get(next_choice(choices)?)?.replay(input, choices),
+        }
+    }
+
     fn could_be_empty(&self) -> bool {
         match &self.inner {
             P::Raw(p) => p.could_be_empty(),
@@ -286,6 +733,65 @@ impl<T: 'static> IsParser for Parser<T> {
         }
     }
 
+    fn find_shadowed(&self, found: &mut Vec<Ambiguity>) {
+        match &self.inner {
+            P::Raw(p) => p.find_shadowed(found),
+            P::Choose { name, options } => {
+                for (i, option) in options.iter().enumerate().skip(1) {
+                    let earlier = DFA::encode(&Parser {
+                        inner: P::Choose {
+                            name: name.clone(),
+                            options: options[..i].to_vec(),
+                        },
+                    });
+                    let dfa = DFA::encode(option);
+                    if dfa.example_not_in(&earlier).is_some() {
+                        continue;
+                    }
+                    if let Some(example) = dfa.example_not_in(&DFA::default()) {
+                        let shadowed = Ambiguity::Shadowed {
+                            choice: name.clone(),
+                            option: option.describe().command,
+                            example,
+                        };
+                        if !found.contains(&shadowed) {
+                            found.push(shadowed);
+                        }
+                    }
+                }
+                for option in options.iter() {
+                    option.find_shadowed(found);
+                }
+            }
+        }
+    }
+
+    fn tag(&self, next_position: &mut usize) -> Tag {
+        match &self.inner {
+            P::Raw(p) => p.tag(next_position),
+            P::Choose { options, .. } => Tag::around(next_position, |next_position| {
+                options.iter().map(|p| p.tag(next_position)).collect()
+            }),
+        }
+    }
+
+    fn parse_path(&self, tag: &Tag, path: &mut &[usize]) -> Option<T> {
+        match &self.inner {
+            P::Raw(p) => p.parse_path(tag, path),
+            P::Choose { options, .. } => {
+                if let Some(i) = tag.parts.iter().position(|t| t.holds(path)) {
+                    return options[i].parse_path(&tag.parts[i], path);
+                }
+                // The path doesn't go through any of the options, so take
+                // the first that is happy with nothing.
+                options
+                    .iter()
+                    .zip(tag.parts.iter())
+                    .find_map(|(option, tag)| option.parse_path(tag, &mut &path[..]))
+            }
+        }
+    }
+
     fn to_grammar(&self, next_position: &mut usize) -> RegularGrammar {
         match &self.inner {
             P::Raw(p) => p.to_grammar(next_position),
@@ -298,33 +804,6 @@ impl<T: 'static> IsParser for Parser<T> {
         }
     }
 
-    fn parse_with_packrat<'a>(
-        &self,
-        input: &'a str,
-        packrat: &mut Packrat,
-    ) -> Result<(Self::Output, &'a str), Error> {
-        match &self.inner {
-            P::Raw(p) => p.parse_with_packrat(input, packrat),
-            P::Choose { options, name } => {
-                packrat.check(name.as_str(), input)?;
-                let mut e = Error::Wrong;
-                for parser in options.iter() {
-                    match parser.parse_with_packrat(input, packrat) {
-                        Ok(v) => {
-                            return Ok(v);
-                        }
-                        Err(Error::Incomplete) => {
-                            e = Error::Incomplete;
-                        }
-                        Err(Error::Wrong) => (),
-                    }
-                }
-                packrat.report(&name, input, e.clone());
-                Err(e)
-            }
-        }
-    }
-
     fn describe(&self) -> Description {
         match &self.inner {
             P::Raw(p) => p.describe(),
@@ -364,26 +843,12 @@ pub fn choose<T, PP: IntoParser<Output = T>>(name: &str, options: Vec<PP>) -> Pa
 impl IsParser for &'static str {
     type Output = &'static str;
     fn parse<'a>(&self, input: &'a str) -> Result<(&'static str, &'a str), Error> {
-        let tag_space = format!("{} ", self);
-        if input == *self {
-            Ok((*self, ""))
-        } else if input.starts_with(&tag_space) {
-            Ok((*self, &input[tag_space.len()..]))
-        } else if self.starts_with(input) {
-            Err(Error::Incomplete)
-        } else {
-            Err(Error::Wrong)
-        }
-    }
-    fn parse_with_packrat<'a>(
-        &self,
-        input: &'a str,
-        _packrat: &mut Packrat,
-    ) -> Result<(Self::Output, &'a str), Error> {
         match input.len().cmp(&self.len()) {
             std::cmp::Ordering::Equal => {
                 if input == *self {
-                    Ok((*self, ""))
+                    // The empty end of `input`, because comparing with the
+                    // dangling pointer of a `""` is strangely slow.
+                    Ok((*self, &input[input.len()..]))
                 } else {
                     Err(Error::Wrong)
                 }
@@ -418,6 +883,15 @@ impl IsParser for &'static str {
         *next_position += bytes.len();
         RegularGrammar::Word { bytes, position }
     }
+
+    fn parse_path(&self, tag: &Tag, path: &mut &[usize]) -> Option<&'static str> {
+        let words = self.split(' ').count();
+        if path.len() < words || path[0] != tag.start {
+            return None;
+        }
+        *path = &path[words..];
+        Some(*self)
+    }
 }
 
 impl IsParser for () {
@@ -425,13 +899,6 @@ impl IsParser for () {
     fn parse<'a>(&self, input: &'a str) -> Result<(Self::Output, &'a str), Error> {
         Ok(((), input))
     }
-    fn parse_with_packrat<'a>(
-        &self,
-        input: &'a str,
-        _packrat: &mut Packrat,
-    ) -> Result<(Self::Output, &'a str), Error> {
-        Ok(((), input))
-    }
     fn describe(&self) -> Description {
         Description {
             command: "".to_string(),
@@ -441,6 +908,10 @@ impl IsParser for () {
     fn to_grammar(&self, _next_position: &mut usize) -> RegularGrammar {
         RegularGrammar::Phrase(Vec::new())
     }
+
+    fn parse_path(&self, _tag: &Tag, _path: &mut &[usize]) -> Option<()> {
+        Some(())
+    }
 }
 
 struct Many1<T>(Parser<T>);
@@ -449,49 +920,68 @@ impl<T: 'static> IsParser for Many1<T> {
     type Output = Vec<T>;
 
     fn parse<'a>(&self, input: &'a str) -> Result<(Self::Output, &'a str), Error> {
-        let (first, mut input) = self.0.parse(input)?;
-        let mut output = vec![first];
-        if input == "" {
-            return Ok((output, input));
-        }
-        loop {
-            match self.0.parse(input) {
-                Ok((v, rest)) => {
-                    output.push(v);
-                    input = rest;
-                    if input == "" {
-                        return Ok((output, input));
-                    }
-                }
-                Err(Error::Incomplete) => return Err(Error::Incomplete),
-                Err(Error::Wrong) => return Ok((output, input)),
-            }
+        parse_ends(self, input)
+    }
+
+    fn ends(&self, input: &str, packrat: &mut Packrat) -> Ends {
+        let once = self.0.ends(input, packrat);
+        let mut ends = Ends {
+            leftovers: Vec::new(),
+            incomplete: once.incomplete,
+        };
+        for leftover in once.leftovers {
+            ends.extend(many_ends(&self.0, rest_of(input, leftover), packrat));
         }
+        ends
     }
 
-    fn parse_with_packrat<'a>(
+    fn parse_exactly(
         &self,
-        input: &'a str,
+        input: &str,
+        leftover: usize,
         packrat: &mut Packrat,
-    ) -> Result<(Self::Output, &'a str), Error> {
-        let (first, mut input) = self.0.parse_with_packrat(input, packrat)?;
+    ) -> Option<Self::Output> {
+        let first = self
+            .0
+            .ends(input, packrat)
+            .leftovers
+            .into_iter()
+            .find(|&first| many_ends(&self.0, rest_of(input, first), packrat).contains(leftover))?;
+        let mut output = vec![self.0.parse_exactly(input, first, packrat)?];
+        many_parse_exactly(
+            &self.0,
+            rest_of(input, first),
+            leftover,
+            &mut output,
+            packrat,
+        )?;
+        Some(output)
+    }
+
+    fn search(
+        &self,
+        input: &str,
+        trace: &mut Trace,
+        then: &mut dyn FnMut(&str, &mut Trace) -> bool,
+    ) -> bool {
+        self.0.search(input, trace, &mut |rest, trace| {
+            many_search(&self.0, rest, trace, then)
+        })
+    }
+
+    fn replay<'a>(
+        &self,
+        input: &'a str,
+        choices: &mut &[usize],
+    ) -> Option<(Self::Output, &'a str)> {
+        let (first, rest) = self.0.replay(input, choices)?;
         let mut output = vec![first];
-        if input == "" {
-            return Ok((output, input));
-        }
-        loop {
-            match self.0.parse_with_packrat(input, packrat) {
-                Ok((v, rest)) => {
-                    output.push(v);
-                    input = rest;
-                    if input == "" {
-                        return Ok((output, input));
-                    }
-                }
-                Err(Error::Incomplete) => return Err(Error::Incomplete),
-                Err(Error::Wrong) => return Ok((output, input)),
-            }
-        }
+        let rest = many_replay(&self.0, rest, choices, &mut output)?;
+        Some((output, rest))
+    }
+
+    fn find_shadowed(&self, found: &mut Vec<Ambiguity>) {
+        self.0.find_shadowed(found)
     }
 
     fn describe(&self) -> Description {
@@ -510,6 +1000,18 @@ impl<T: 'static> IsParser for Many1<T> {
             RegularGrammar::Many0(Box::new(self.0.to_grammar(next_position))),
         ])
     }
+
+    fn tag(&self, next_position: &mut usize) -> Tag {
+        Tag::around(next_position, |next_position| {
+            vec![self.0.tag(next_position), self.0.tag(next_position)]
+        })
+    }
+
+    fn parse_path(&self, tag: &Tag, path: &mut &[usize]) -> Option<Self::Output> {
+        let mut output = vec![self.0.parse_path(&tag.parts[0], path)?];
+        many_parse_path(&self.0, &tag.parts[1], path, &mut output)?;
+        Some(output)
+    }
 }
 
 struct Many0<T>(Parser<T>);
@@ -521,42 +1023,46 @@ impl<T: 'static> IsParser for Many0<T> {
         true
     }
 
-    fn parse<'a>(&self, mut input: &'a str) -> Result<(Self::Output, &'a str), Error> {
-        let mut output = Vec::new();
-        loop {
-            match self.0.parse(input) {
-                Ok((v, rest)) => {
-                    output.push(v);
-                    input = rest;
-                    if input == "" {
-                        return Ok((output, input));
-                    }
-                }
-                Err(Error::Incomplete) => return Err(Error::Incomplete),
-                Err(Error::Wrong) => return Ok((output, input)),
-            }
-        }
+    fn parse<'a>(&self, input: &'a str) -> Result<(Self::Output, &'a str), Error> {
+        parse_ends(self, input)
+    }
+
+    fn ends(&self, input: &str, packrat: &mut Packrat) -> Ends {
+        many_ends(&self.0, input, packrat)
     }
 
-    fn parse_with_packrat<'a>(
+    fn parse_exactly(
         &self,
-        mut input: &'a str,
+        input: &str,
+        leftover: usize,
         packrat: &mut Packrat,
-    ) -> Result<(Self::Output, &'a str), Error> {
+    ) -> Option<Self::Output> {
         let mut output = Vec::new();
-        loop {
-            match self.0.parse_with_packrat(input, packrat) {
-                Ok((v, rest)) => {
-                    output.push(v);
-                    input = rest;
-                    if input == "" {
-                        return Ok((output, input));
-                    }
-                }
-                Err(Error::Incomplete) => return Err(Error::Incomplete),
-                Err(Error::Wrong) => return Ok((output, input)),
-            }
-        }
+        many_parse_exactly(&self.0, input, leftover, &mut output, packrat)?;
+        Some(output)
+    }
+
+    fn search(
+        &self,
+        input: &str,
+        trace: &mut Trace,
+        then: &mut dyn FnMut(&str, &mut Trace) -> bool,
+    ) -> bool {
+        many_search(&self.0, input, trace, then)
+    }
+
+    fn replay<'a>(
+        &self,
+        input: &'a str,
+        choices: &mut &[usize],
+    ) -> Option<(Self::Output, &'a str)> {
+        let mut output = Vec::new();
+        let rest = many_replay(&self.0, input, choices, &mut output)?;
+        Some((output, rest))
+    }
+
+    fn find_shadowed(&self, found: &mut Vec<Ambiguity>) {
+        self.0.find_shadowed(found)
     }
 
     fn describe(&self) -> Description {
@@ -571,6 +1077,120 @@ impl<T: 'static> IsParser for Many0<T> {
     fn to_grammar(&self, next_position: &mut usize) -> RegularGrammar {
         RegularGrammar::Many0(Box::new(self.0.to_grammar(next_position)))
     }
+
+    fn tag(&self, next_position: &mut usize) -> Tag {
+        Tag::around(next_position, |next_position| {
+            vec![self.0.tag(next_position)]
+        })
+    }
+
+    fn parse_path(&self, tag: &Tag, path: &mut &[usize]) -> Option<Self::Output> {
+        let mut output = Vec::new();
+        many_parse_path(&self.0, &tag.parts[0], path, &mut output)?;
+        Some(output)
+    }
+}
+
+/// Everywhere any number of repeats of `parser` could finish, most repeats
+/// first.
+fn many_ends<T: 'static>(parser: &Parser<T>, input: &str, packrat: &mut Packrat) -> Ends {
+    let key = (parser as *const Parser<T> as usize, input.len());
+    if let Some(ends) = packrat.repeats.get(&key) {
+        return ends.clone();
+    }
+    let once = parser.ends(input, packrat);
+    let mut ends = Ends {
+        leftovers: Vec::new(),
+        incomplete: once.incomplete,
+    };
+    for leftover in once.leftovers {
+        // Each repeat must eat something, or we would never stop.
+        if leftover < input.len() {
+            ends.extend(many_ends(parser, rest_of(input, leftover), packrat));
+        }
+    }
+    ends.push(input.len());
+    packrat.repeats.insert(key, ends.clone());
+    ends
+}
+
+/// Parse repeats of `parser` onto `output` until exactly `leftover` bytes of
+/// `input` are left.
+fn many_parse_exactly<T: 'static>(
+    parser: &Parser<T>,
+    mut input: &str,
+    leftover: usize,
+    output: &mut Vec<T>,
+    packrat: &mut Packrat,
+) -> Option<()> {
+    loop {
+        let next = parser
+            .ends(input, packrat)
+            .leftovers
+            .into_iter()
+            .find(|&next| {
+                next < input.len()
+                    && many_ends(parser, rest_of(input, next), packrat).contains(leftover)
+            });
+        match next {
+            Some(next) => {
+                output.push(parser.parse_exactly(input, next, packrat)?);
+                input = rest_of(input, next);
+            }
+            None if input.len() == leftover => return Some(()),
+            None => return None,
+        }
+    }
+}
+
+/// Search through any number of repeats of `parser`, most repeats first.
+fn many_search<T: 'static>(
+    parser: &Parser<T>,
+    input: &str,
+    trace: &mut Trace,
+    then: &mut dyn FnMut(&str, &mut Trace) -> bool,
+) -> bool {
+    // Each repeat must eat something, or we would never stop.
+    let another = !input.is_empty()
+        && trace.choose(1, |trace| {
+            parser.search(input, trace, &mut |rest, trace| {
+                rest.len() < input.len() && many_search(parser, rest, trace, then)
+            })
+        });
+    another || trace.choose(0, |trace| then(input, trace))
+}
+
+/// Replay repeats of `parser` onto `output` until `choices` says to stop.
+fn many_replay<'a, T: 'static>(
+    parser: &Parser<T>,
+    mut input: &'a str,
+    choices: &mut &[usize],
+    output: &mut Vec<T>,
+) -> Option<&'a str> {
+    while next_choice(choices)? == 1 {
+        let (value, rest) = parser.replay(input, choices)?;
+        output.push(value);
+        input = rest;
+    }
+    Some(input)
+}
+
+/// Build repeats of `parser` onto `output` for as long as `path` goes
+/// through the positions in `tag`.
+fn many_parse_path<T: 'static>(
+    parser: &Parser<T>,
+    tag: &Tag,
+    path: &mut &[usize],
+    output: &mut Vec<T>,
+) -> Option<()> {
+    while tag.holds(path) {
+        let before = path.len();
+        output.push(parser.parse_path(tag, path)?);
+        if path.len() == before {
+            return None;
+        }
+    }
+    Some(())
 }
 
 struct Optional<T>(Parser<T>);
@@ -579,25 +1199,57 @@ impl<T: 'static> IsParser for Optional<T> {
     type Output = Option<T>;
 
     fn parse<'a>(&self, input: &'a str) -> Result<(Self::Output, &'a str), Error> {
-        match self.0.parse(input) {
-            Ok((v, rest)) => Ok((Some(v), rest)),
-            Err(Error::Incomplete) => Err(Error::Incomplete),
-            Err(Error::Wrong) => Ok((None, input)),
+        parse_ends(self, input)
+    }
+
+    fn ends(&self, input: &str, packrat: &mut Packrat) -> Ends {
+        let mut ends = self.0.ends(input, packrat);
+        ends.push(input.len());
+        ends
+    }
+
+    fn parse_exactly(
+        &self,
+        input: &str,
+        leftover: usize,
+        packrat: &mut Packrat,
+    ) -> Option<Self::Output> {
+        if self.0.ends(input, packrat).contains(leftover) {
+            Some(Some(self.0.parse_exactly(input, leftover, packrat)?))
+        } else if input.len() == leftover {
+            Some(None)
+        } else {
+            None
         }
     }
 
-    fn parse_with_packrat<'a>(
+    fn search(
+        &self,
+        input: &str,
+        trace: &mut Trace,
+        then: &mut dyn FnMut(&str, &mut Trace) -> bool,
+    ) -> bool {
+        trace.choose(1, |trace| self.0.search(input, trace, then))
+            || trace.choose(0, |trace| then(input, trace))
+    }
+
+    fn replay<'a>(
         &self,
         input: &'a str,
-        rat: &mut Packrat,
-    ) -> Result<(Self::Output, &'a str), Error> {
-        match self.0.parse_with_packrat(input, rat) {
-            Ok((v, rest)) => Ok((Some(v), rest)),
-            Err(Error::Incomplete) => Err(Error::Incomplete),
-            Err(Error::Wrong) => Ok((None, input)),
+        choices: &mut &[usize],
+    ) -> Option<(Self::Output, &'a str)> {
+        if next_choice(choices)? == 1 {
+            let (value, rest) = self.0.replay(input, choices)?;
+            Some((Some(value), rest))
+        } else {
+            Some((None, input))
         }
     }
 
+    fn find_shadowed(&self, found: &mut Vec<Ambiguity>) {
+        self.0.find_shadowed(found)
+    }
+
     fn describe(&self) -> Description {
         let mut d = self.0.describe();
         if d.command.contains(' ') {
@@ -614,6 +1266,20 @@ impl<T: 'static> IsParser for Optional<T> {
             RegularGrammar::Phrase(Vec::new()),
         ])
     }
+
+    fn tag(&self, next_position: &mut usize) -> Tag {
+        Tag::around(next_position, |next_position| {
+            vec![self.0.tag(next_position)]
+        })
+    }
+
+    fn parse_path(&self, tag: &Tag, path: &mut &[usize]) -> Option<Self::Output> {
+        // Like parsing, we would rather have something than nothing.
+        if tag.parts[0].holds(path) {
+            return Some(Some(self.0.parse_path(&tag.parts[0], path)?));
+        }
+        Some(self.0.parse_path(&tag.parts[0], &mut &path[..]))
+    }
 }
 
 impl<T: 'static, P2: IntoParser> std::ops::Add<P2> for Parser<T> {
@@ -798,3 +1464,170 @@ fn checking() {
     assert_eq!(Err(Error::Incomplete), dfa.check("sing fa la do"));
     assert_eq!(Err(Error::Wrong), dfa.check("sing fa la re"));
 }
+
+#[test]
+fn diagnostics() {
+    let diagnose = roundy::parser().to_diagnoser();
+    assert_eq!(Ok(()), diagnose("spell alpha"));
+    let e = diagnose("spell alpha sneeze").unwrap_err();
+    assert_eq!(Error::Wrong, e.error);
+    assert_eq!(2, e.offset);
+    assert_eq!(
+        "after 'spell alpha' expected one of: <NATO>, big, <digit>",
+        e.to_string()
+    );
+    let e = diagnose("spell").unwrap_err();
+    assert_eq!(Error::Incomplete, e.error);
+    assert_eq!(
+        "after 'spell' expected one of: <NATO>, big, <digit>",
+        e.to_string()
+    );
+    // A word we only have the start of is where it went wrong.
+    let e = diagnose("five lef").unwrap_err();
+    assert_eq!((Error::Incomplete, 1), (e.error, e.offset));
+
+    let diagnose = (choose("<food>", vec!["peas", "peas and corn"]) + "please").to_diagnoser();
+    let e = expect_test::expect![[r#"
+        expected peas
+        after 'peas' expected one of: please, and
+        after 'peas and corn please' expected nothing more
+    "#]];
+    let errors: String = ["", "peas corn", "peas and corn please now"]
+        .iter()
+        .map(|s| format!("{}\n", diagnose(s).unwrap_err()))
+        .collect();
+    e.assert_eq(&errors);
+}
+
+#[test]
+fn completions() {
+    let dfa =
+        DFA::encode(&("sing".then(choose("<note>", vec!["fa", "la", "la la"]).many0()) + "done"));
+    assert_eq!(
+        Some(Completions {
+            next_words: vec!["sing".to_string()],
+            can_end: false
+        }),
+        dfa.completions("")
+    );
+    assert_eq!(
+        Some(Completions {
+            next_words: vec!["done".to_string(), "fa".to_string(), "la".to_string()],
+            can_end: false
+        }),
+        dfa.completions("sing la")
+    );
+    assert!(dfa.completions("sing la done").unwrap().can_end);
+    assert_eq!(None, dfa.completions("sing re"));
+    assert_eq!(None, dfa.completions("sin"));
+
+    let examples: Vec<String> = dfa.example_completions("sing", 2).collect();
+    assert_eq!(vec!["done", "fa done", "la done"], examples);
+    let examples: Vec<String> = dfa.example_completions("", 10).take(4).collect();
+    assert_eq!(
+        vec![
+            "sing done",
+            "sing fa done",
+            "sing la done",
+            "sing fa fa done"
+        ],
+        examples
+    );
+    assert_eq!(0, dfa.example_completions("sing re", 3).count());
+}
+
+#[test]
+fn backtracking() {
+    let p = choose("<food>", vec!["peas", "peas and corn"]) + "please";
+    assert_eq!(
+        Ok((("peas and corn", "please"), "")),
+        p.parse("peas and corn please")
+    );
+    assert_eq!(Ok((("peas", "please"), "")), p.parse("peas please"));
+    assert_eq!(Err(Error::Incomplete), p.parse("peas and"));
+    assert_eq!(
+        Ok((("peas and corn", "please"), "now")),
+        p.parse("peas and corn please now")
+    );
+
+    // Repeats give back what comes after them.
+    let p = "la".many0() + "la la";
+    assert_eq!(Ok(((vec!["la"], "la la"), "")), p.parse("la la la"));
+    let p = choose("<note>", vec!["fa", "la"]).many1() + "la";
+    assert_eq!(Ok(((vec!["fa", "la"], "la"), "")), p.parse("fa la la"));
+    let p = "fa".optional() + "fa";
+    assert_eq!(Ok(((None, "fa"), "")), p.parse("fa"));
+}
+
+#[test]
+fn backtracking_through_ambiguity() {
+    // There are exponentially many ways to group these notes, far too many
+    // to try one at a time.
+    let p = choose("<notes>", vec!["la", "la la"]).many1() + "fa";
+    let notes = vec!["la"; 40].join(" ");
+    assert_eq!(Err(Error::Incomplete), p.parse(&notes));
+    assert_eq!(
+        Ok(((vec!["la"; 40], "fa"), "")),
+        p.parse(&format!("{notes} fa"))
+    );
+    assert_eq!(
+        Ok(((vec!["la"; 40], "fa"), "so")),
+        p.parse(&format!("{notes} fa so"))
+    );
+}
+
+/// Phrases from `parser`'s grammar, their beginnings, and some that are a
+/// word too long or have a word changed, to try parsers out on.
+#[cfg(test)]
+fn test_inputs<P: IsParser>(parser: &P) -> Vec<String> {
+    use generate::{GeneratorConfig, SentenceGenerator};
+
+    let generator = SentenceGenerator::new(parser, &GeneratorConfig::default());
+    let sentences = generator.sample(100, 1);
+    let words: Vec<&str> = sentences.iter().flat_map(|s| s.split(' ')).collect();
+    let mut inputs = Vec::new();
+    for (i, sentence) in sentences.iter().enumerate() {
+        let sentence: Vec<&str> = sentence.split(' ').collect();
+        for n in 0..=sentence.len() {
+            inputs.push(sentence[..n].join(" "));
+        }
+        let mut longer = sentence.clone();
+        longer.push(words[i % words.len()]);
+        inputs.push(longer.join(" "));
+        for j in 0..sentence.len() {
+            let mut changed = sentence.clone();
+            changed[j] = words[(i + j) % words.len()];
+            inputs.push(changed.join(" "));
+        }
+    }
+    inputs
+}
+
+#[test]
+fn parser_agrees_with_checker() {
+    fn cross_check<T: 'static>(parser: Parser<T>) {
+        let check = parser.to_checker();
+        for input in test_inputs(&parser) {
+            let parsed = matches!(parser.parse(&input), Ok((_, "")));
+            assert_eq!(check(&input).is_ok(), parsed, "{input:?}");
+        }
+    }
+
+    cross_check(choose("<food>", vec!["peas", "peas and corn"]) + "please");
+    cross_check(choose("<note>", vec!["fa", "la", "fa la"]).many1() + "la".many0());
+    cross_check("sing".then(choose("<note>", vec!["fa", "la"]).many0().then("done")));
+    cross_check(number::number());
+    cross_check("la".many0() + "la".many0() + "fa");
+    // The shape of roundy's commands, without building actions that repeat
+    // a keystroke a million times.
+    let navigation = number::number().optional() + spelling::control_keys().many1();
+    cross_check(choose(
+        "<command>",
+        vec![
+            "spell".then(spelling::extended_nato().many1()).map(|_| ()),
+            (spelling::modifiers().many1() + spelling::nato()).map(|_| ()),
+            navigation.many1().map(|_| ()),
+            (number::number() + "blind mice").map(|_| ()),
+        ],
+    ));
+}
@@ -0,0 +1,109 @@
+//! Finding where a grammar can be read more than one way.  The parser
+//! quietly takes the first option that fits, which can be surprising, so
+//! it is better to find out from a test.
+
+use super::regular::ambiguous_phrases;
+use super::IsParser;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ambiguity {
+    /// A whole phrase that can be parsed more than one way.
+    Phrase(String),
+    /// An option of a `choose` that is never chosen, because the options
+    /// before it accept everything it does, such as `example`.
+    Shadowed {
+        choice: String,
+        option: String,
+        example: String,
+    },
+}
+
+impl std::fmt::Display for Ambiguity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Ambiguity::Phrase(phrase) => write!(f, "{phrase:?} can be parsed more than one way"),
+            Ambiguity::Shadowed {
+                choice,
+                option,
+                example,
+            } => write!(
+                f,
+                "{choice} never chooses {option}, since earlier options accept everything it does, such as {example:?}"
+            ),
+        }
+    }
+}
+
+/// Everything ambiguous about `parser`: the shortest ambiguous phrase for
+/// each place where two readings part, and then any shadowed options.
+pub fn ambiguities<P: IsParser + ?Sized>(parser: &P) -> Vec<Ambiguity> {
+    let mut found: Vec<Ambiguity> = ambiguous_phrases(parser)
+        .into_iter()
+        .map(Ambiguity::Phrase)
+        .collect();
+    parser.find_shadowed(&mut found);
+    found
+}
+
+/// Panic, listing the ambiguities, unless every phrase `parser` accepts can
+/// only be read one way.
+pub fn assert_unambiguous<P: IsParser + ?Sized>(parser: &P) {
+    let found = ambiguities(parser);
+    if !found.is_empty() {
+        let found: Vec<String> = found.iter().map(|a| format!("    {a}")).collect();
+        panic!(
+            "{} is ambiguous:\n{}",
+            parser.describe().command,
+            found.join("\n")
+        );
+    }
+}
+
+#[cfg(test)]
+fn list(found: Vec<Ambiguity>) -> String {
+    found.iter().map(|a| format!("{a}\n")).collect()
+}
+
+#[test]
+fn find_ambiguities() {
+    use super::{choose, IntoParser};
+
+    // Backtracking sorts these out, so they aren't ambiguous.
+    assert_unambiguous(&(choose("<food>", vec!["peas", "peas and corn"]) + "please"));
+    assert_unambiguous(&("la".many0() + "fa".many0()));
+
+    assert_eq!(
+        vec![Ambiguity::Phrase("la la".to_string())],
+        ambiguities(&("la".many0() + "la".many1()))
+    );
+    let e = expect_test::expect![[r#"
+        "a" can be parsed more than one way
+        <letter> never chooses a, since earlier options accept everything it does, such as "a"
+    "#]];
+    e.assert_eq(&list(ambiguities(&choose("<letter>", vec!["a", "b", "a"]))));
+}
+
+#[test]
+#[should_panic(expected = "\"a\" can be parsed more than one way")]
+fn assert_ambiguous() {
+    use super::choose;
+    assert_unambiguous(&choose("<letter>", vec!["a", "a b", "a"]));
+}
+
+#[test]
+fn spelling_ambiguities() {
+    use super::{number, spelling, IntoParser};
+
+    assert_unambiguous(&number::number());
+    assert_unambiguous(&spelling::extended_nato().many1());
+    assert_unambiguous(&spelling::modifiers().many1());
+
+    // Arrows are navigation keys too.
+    let e = expect_test::expect![[r#"
+        "up" can be parsed more than one way
+        "down" can be parsed more than one way
+        "left" can be parsed more than one way
+        "right" can be parsed more than one way
+    "#]];
+    e.assert_eq(&list(ambiguities(&spelling::control_keys())));
+}
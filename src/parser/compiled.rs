@@ -0,0 +1,188 @@
+//! Parsing with the grammar's automata, so that reading a phrase is a pass
+//! over its bytes, and the only parsers we run are the ones it is made of.
+
+use super::regular::{Positions, RegularGrammar};
+use super::{Ambiguity, Description, Ends, Error, IsParser, Packrat, Parser, Tag, Trace};
+
+/// A [`Parser`] that finds the way through its grammar that a phrase takes
+/// before building anything.  It gives the same results as the parser, but
+/// phrases we can't parse are turned away by the grammar's DFA, and the
+/// rest are built straight from their path, taking the way the parser would
+/// where there is more than one.  Anything else, such as a phrase with more
+/// after it, or one whose path could have come more than one way through
+/// the grammar, is left to the parser.
+pub struct CompiledParser<T> {
+    parser: Parser<T>,
+    positions: Positions,
+    tag: Tag,
+}
+
+impl<T: 'static> Parser<T> {
+    pub fn compile(&self) -> CompiledParser<T> {
+        CompiledParser {
+            parser: self.clone(),
+            positions: Positions::encode(self),
+            // Numbered like `Positions`, after the end of the phrase at 0.
+            tag: self.tag(&mut 1),
+        }
+    }
+}
+
+impl<T: 'static> CompiledParser<T> {
+    pub fn parser(&self) -> &Parser<T> {
+        &self.parser
+    }
+
+    /// Build the output for all of `input` straight from its path.
+    fn parse_whole(&self, input: &str) -> Option<T> {
+        let path = self.positions.path(input)?;
+        let mut path = path.as_slice();
+        let value = self.parser.parse_path(&self.tag, &mut path)?;
+        path.is_empty().then_some(value)
+    }
+}
+
+impl<T: 'static> IsParser for CompiledParser<T> {
+    type Output = T;
+    fn parse<'a>(&self, input: &'a str) -> Result<(T, &'a str), Error> {
+        // Finding the path reads the phrase with the DFA, so there's no
+        // need to check a phrase we could build.
+        if let Some(value) = self.parse_whole(input) {
+            return Ok((value, ""));
+        }
+        match self.positions.dfa.check(input) {
+            Ok(()) => (),
+            // Saying nothing would do, whatever comes next.
+            Err(_) if self.positions.dfa.accepts_nothing() => (),
+            // Nothing to be had from the start of the phrase either.
+            Err(e) if !self.positions.dfa.finishes_early(input) => return Err(e),
+            Err(_) => (),
+        }
+        self.parser.parse(input)
+    }
+
+    fn ends(&self, input: &str, packrat: &mut Packrat) -> Ends {
+        self.parser.ends(input, packrat)
+    }
+
+    fn parse_exactly(&self, input: &str, leftover: usize, packrat: &mut Packrat) -> Option<T> {
+        self.parser.parse_exactly(input, leftover, packrat)
+    }
+
+    fn search(
+        &self,
+        input: &str,
+        trace: &mut Trace,
+        then: &mut dyn FnMut(&str, &mut Trace) -> bool,
+    ) -> bool {
+        self.parser.search(input, trace, then)
+    }
+
+    fn replay<'a>(&self, input: &'a str, choices: &mut &[usize]) -> Option<(T, &'a str)> {
+        self.parser.replay(input, choices)
+    }
+
+    fn describe(&self) -> Description {
+        self.parser.describe()
+    }
+
+    fn could_be_empty(&self) -> bool {
+        self.parser.could_be_empty()
+    }
+
+    fn find_shadowed(&self, found: &mut Vec<Ambiguity>) {
+        self.parser.find_shadowed(found)
+    }
+
+    fn to_grammar(&self, next_position: &mut usize) -> RegularGrammar {
+        self.parser.to_grammar(next_position)
+    }
+
+    fn tag(&self, next_position: &mut usize) -> Tag {
+        self.parser.tag(next_position)
+    }
+
+    fn parse_path(&self, tag: &Tag, path: &mut &[usize]) -> Option<T> {
+        self.parser.parse_path(tag, path)
+    }
+}
+
+#[test]
+fn compiled_agrees_with_parser() {
+    use super::{choose, number, spelling, test_inputs, IntoParser};
+
+    fn cross_check<T: 'static + std::fmt::Debug>(parser: Parser<T>) {
+        cross_check_where(parser, |_| true)
+    }
+    fn cross_check_where<T: 'static + std::fmt::Debug>(
+        parser: Parser<T>,
+        keep: impl Fn(&str) -> bool,
+    ) {
+        let compiled = parser.compile();
+        for input in test_inputs(&parser).into_iter().filter(|i| keep(i)) {
+            assert_eq!(
+                format!("{:?}", parser.parse(&input)),
+                format!("{:?}", compiled.parse(&input)),
+                "{input:?}"
+            );
+        }
+    }
+
+    cross_check(choose("<food>", vec!["peas", "peas and corn"]) + "please");
+    cross_check(choose("<note>", vec!["fa", "la", "fa la"]).many1() + "la".many0());
+    cross_check("sing".then(choose("<note>", vec!["fa", "la"]).many0().then("done")));
+    cross_check("fa".optional() + "la".many0().optional() + "fa");
+    cross_check(number::number());
+    cross_check(number::number().optional() + spelling::control_keys().many1());
+    cross_check(spelling::extended_nato().many1());
+    // More than one way to read these.
+    cross_check("la".many0() + "la".many0());
+    cross_check(choose("<letter>", vec!["a", "a b", "a"]).many1() + "b".optional());
+    cross_check(number::number().optional() + spelling::control_keys());
+    // Carrying on with a repeat comes before starting the next one out.
+    cross_check("la".many1().many0() + "fa".optional());
+    cross_check(choose("<notes>", vec!["la".many1(), "fa".many1()]).many0());
+    // "la fa" is one repeat or two, the second starting with nothing.
+    let end = choose("<end>", vec!["so".optional(), "fa".map(Some)]);
+    cross_check(("la".optional() + end).many0());
+    // Roundy presses keys as many times as it is told, so leave out the
+    // big numbers.
+    cross_check_where(super::roundy::parser(), |input| {
+        !input.contains("thousand") && !input.contains("million")
+    });
+}
+
+#[test]
+fn compiled_takes_the_path() {
+    use super::{choose, number, IntoParser};
+
+    let p = (choose("<food>", vec!["peas", "peas and corn"]) + "please").compile();
+    assert_eq!(
+        Some(("peas and corn", "please")),
+        p.parse_whole("peas and corn please")
+    );
+    assert_eq!(Some(("peas", "please")), p.parse_whole("peas please"));
+    assert_eq!(Err(Error::Incomplete), p.parse("peas and"));
+    assert_eq!(Err(Error::Wrong), p.parse("corn"));
+    // The phrase is there, but there's more.
+    assert_eq!(
+        Ok((("peas", "please"), "thank you")),
+        p.parse("peas please thank you")
+    );
+
+    let p = number::number().compile();
+    assert_eq!(Some(321), p.parse_whole("three hundred twenty one"));
+    assert_eq!(Some(7_020), p.parse_whole("seven thousand twenty"));
+
+    // Two ways to read it, so we read it the way the parser would.
+    let p = ("la".many0() + "la".many0()).compile();
+    assert_eq!(Some((vec!["la", "la"], vec![])), p.parse_whole("la la"));
+
+    // Arrows are navigation keys as well as control keys.
+    let p = super::roundy::parser().compile();
+    for phrase in ["one up", "five left", "left", "two up three left left"] {
+        let (parsed, _) = p.parser().parse(phrase).unwrap();
+        let whole = p.parse_whole(phrase).unwrap();
+        assert_eq!(format!("{parsed:?}"), format!("{whole:?}"), "{phrase:?}");
+    }
+}
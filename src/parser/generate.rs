@@ -0,0 +1,305 @@
+//! Generating the sentences a grammar accepts, e.g. to build a text corpus
+//! for an n-gram language model that knows our commands.
+
+use super::regular::RegularGrammar;
+use super::IsParser;
+
+/// How likely each option of a `choose` is to be picked when sampling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Weighting {
+    /// Every option is equally likely, so short lists of keywords turn up
+    /// as often as, say, all the numbers put together.
+    Uniform,
+    /// Options are weighted by how many phrases they can produce, so every
+    /// phrase is equally likely.
+    ByPhrases,
+}
+
+impl std::str::FromStr for Weighting {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "uniform" => Ok(Weighting::Uniform),
+            "by-phrases" => Ok(Weighting::ByPhrases),
+            _ => Err(anyhow::anyhow!(
+                "The weighting must be uniform or by-phrases, not {s:?}"
+            )),
+        }
+    }
+}
+
+/// How to generate sentences.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneratorConfig {
+    /// The most times anything repeated with `many0` is repeated (or, for
+    /// `many1`, the most times it is repeated after the first).
+    pub max_repeats: usize,
+    pub weighting: Weighting,
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        GeneratorConfig {
+            max_repeats: 2,
+            weighting: Weighting::Uniform,
+        }
+    }
+}
+
+/// Produces sentences from a grammar.
+pub struct SentenceGenerator {
+    grammar: RegularGrammar,
+    config: GeneratorConfig,
+}
+
+impl SentenceGenerator {
+    pub fn new(parser: &impl IsParser, config: &GeneratorConfig) -> Self {
+        SentenceGenerator {
+            grammar: parser.to_grammar(&mut 0),
+            config: config.clone(),
+        }
+    }
+
+    /// How many sentences there are, within the repetition limit.  This can
+    /// be very large indeed.
+    pub fn count(&self) -> f64 {
+        count(&self.grammar, self.config.max_repeats)
+    }
+
+    /// The first `limit` sentences (or all of them, if there are fewer).
+    pub fn enumerate(&self, limit: usize) -> Vec<String> {
+        if limit == 0 {
+            return Vec::new();
+        }
+        enumerate(&self.grammar, self.config.max_repeats, limit)
+            .into_iter()
+            .map(|words| words.join(" "))
+            .collect()
+    }
+
+    /// Pick `n` sentences at random.  The same `seed` always gives the same
+    /// sentences.
+    pub fn sample(&self, n: usize, seed: u64) -> Vec<String> {
+        let mut rng = Rng::new(seed);
+        (0..n)
+            .map(|_| {
+                let mut words = Vec::new();
+                self.sample_into(&self.grammar, &mut rng, &mut words);
+                words.join(" ")
+            })
+            .collect()
+    }
+
+    fn sample_into(&self, g: &RegularGrammar, rng: &mut Rng, words: &mut Vec<String>) {
+        let max_repeats = self.config.max_repeats;
+        match g {
+            RegularGrammar::Word { .. } => words.extend(word(g)),
+            RegularGrammar::Phrase(v) => {
+                for g in v {
+                    self.sample_into(g, rng, words);
+                }
+            }
+            RegularGrammar::Choice(v) => {
+                let weights: Vec<f64> = match self.config.weighting {
+                    Weighting::Uniform => vec![1.0; v.len()],
+                    Weighting::ByPhrases => v.iter().map(|g| count(g, max_repeats)).collect(),
+                };
+                if let Some(i) = rng.pick(&weights) {
+                    self.sample_into(&v[i], rng, words);
+                }
+            }
+            RegularGrammar::Many0(g) => {
+                let weights: Vec<f64> = match self.config.weighting {
+                    Weighting::Uniform => vec![1.0; max_repeats + 1],
+                    Weighting::ByPhrases => {
+                        let n = count(g, max_repeats);
+                        (0..=max_repeats).map(|k| n.powi(k as i32)).collect()
+                    }
+                };
+                for _ in 0..rng.pick(&weights).unwrap_or(0) {
+                    self.sample_into(g, rng, words);
+                }
+            }
+        }
+    }
+}
+
+/// The word in a [`RegularGrammar::Word`], which starts with a space.
+fn word(g: &RegularGrammar) -> Option<String> {
+    match g {
+        RegularGrammar::Word { bytes, .. } => {
+            let w = String::from_utf8_lossy(bytes).trim().to_string();
+            Some(w).filter(|w| !w.is_empty())
+        }
+        _ => None,
+    }
+}
+
+fn count(g: &RegularGrammar, max_repeats: usize) -> f64 {
+    match g {
+        RegularGrammar::Word { .. } => 1.0,
+        RegularGrammar::Phrase(v) => v.iter().map(|g| count(g, max_repeats)).product(),
+        RegularGrammar::Choice(v) => v.iter().map(|g| count(g, max_repeats)).sum(),
+        RegularGrammar::Many0(g) => {
+            let n = count(g, max_repeats);
+            (0..=max_repeats).map(|k| n.powi(k as i32)).sum()
+        }
+    }
+}
+
+fn enumerate(g: &RegularGrammar, max_repeats: usize, limit: usize) -> Vec<Vec<String>> {
+    let product = |heads: Vec<Vec<String>>, tails: &[Vec<String>]| {
+        let mut out = Vec::new();
+        'outer: for head in heads {
+            for tail in tails {
+                if out.len() == limit {
+                    break 'outer;
+                }
+                let mut words = head.clone();
+                words.extend(tail.iter().cloned());
+                out.push(words);
+            }
+        }
+        out
+    };
+    match g {
+        RegularGrammar::Word { .. } => vec![word(g).into_iter().collect()],
+        RegularGrammar::Phrase(v) => {
+            let mut out = vec![Vec::new()];
+            for g in v {
+                out = product(out, &enumerate(g, max_repeats, limit));
+            }
+            out
+        }
+        RegularGrammar::Choice(v) => {
+            let mut out = Vec::new();
+            for g in v {
+                out.extend(enumerate(g, max_repeats, limit - out.len()));
+                if out.len() == limit {
+                    break;
+                }
+            }
+            out
+        }
+        RegularGrammar::Many0(g) => {
+            let once = enumerate(g, max_repeats, limit);
+            let mut repeated = vec![Vec::new()];
+            let mut out = repeated.clone();
+            for _ in 0..max_repeats {
+                if out.len() >= limit {
+                    break;
+                }
+                repeated = product(repeated, &once);
+                out.extend(repeated.iter().take(limit - out.len()).cloned());
+            }
+            out.truncate(limit);
+            out
+        }
+    }
+}
+
+/// A small, predictable random number generator (xorshift64*), so that a
+/// corpus can be regenerated exactly.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next_f64(&mut self) -> f64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        let x = self.0.wrapping_mul(0x2545_F491_4F6C_DD1D);
+        (x >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Pick an index with probability in proportion to its weight.
+    fn pick(&mut self, weights: &[f64]) -> Option<usize> {
+        let total: f64 = weights.iter().sum();
+        if weights.is_empty() || total <= 0.0 {
+            return None;
+        }
+        let mut x = self.next_f64() * total;
+        for (i, w) in weights.iter().enumerate() {
+            if x < *w {
+                return Some(i);
+            }
+            x -= w;
+        }
+        Some(weights.len() - 1)
+    }
+}
+
+#[test]
+fn enumerate_sentences() {
+    use super::{choose, IntoParser};
+
+    let grammar = choose("direction", vec!["up", "down"]).many1() + "please".optional();
+    let generator = SentenceGenerator::new(&grammar, &GeneratorConfig::default());
+    assert_eq!(generator.count(), 28.0);
+    let e = expect_test::expect![[r#"
+        [
+            "up please",
+            "up",
+            "up up please",
+            "up up",
+            "up down please",
+            "up down",
+            "up up up please",
+            "up up up",
+            "up up down please",
+            "up up down",
+        ]
+    "#]];
+    e.assert_debug_eq(&generator.enumerate(10));
+    assert_eq!(generator.enumerate(100).len(), 28);
+}
+
+#[test]
+fn sample_sentences() {
+    use super::roundy;
+
+    let parser = roundy::parser();
+    let check = parser.to_checker();
+    for weighting in [Weighting::Uniform, Weighting::ByPhrases] {
+        let config = GeneratorConfig {
+            max_repeats: 1,
+            weighting,
+        };
+        let generator = SentenceGenerator::new(&parser, &config);
+        let sentences = generator.sample(200, 42);
+        assert_eq!(sentences, generator.sample(200, 42));
+        for sentence in &sentences {
+            assert_eq!(Ok(()), check(sentence), "{sentence:?}");
+        }
+    }
+}
+
+#[test]
+fn weighting_options() {
+    use super::{choose, IntoParser};
+
+    let grammar = choose(
+        "thing",
+        vec![
+            "testing".into_parser(),
+            choose("number", vec!["one", "two", "three"])
+                .map(|_| "number")
+                .many1()
+                .map(|_| "numbers"),
+        ],
+    );
+    let testing = |weighting| {
+        let config = GeneratorConfig {
+            max_repeats: 1,
+            weighting,
+        };
+        let sentences = SentenceGenerator::new(&grammar, &config).sample(1000, 7);
+        sentences.iter().filter(|s| *s == "testing").count()
+    };
+    // One in two, versus one in thirteen.
+    assert!((400..600).contains(&testing(Weighting::Uniform)));
+    assert!((30..120).contains(&testing(Weighting::ByPhrases)));
+}
@@ -1,8 +1,9 @@
+use std::collections::{hash_map::Entry, HashMap, VecDeque};
 use std::fmt::Debug;
 
 use tinyset::SetUsize;
 
-use crate::parser::Error;
+use crate::parser::{Error, ParseError};
 
 use super::IsParser;
 
@@ -72,10 +73,15 @@ impl RegularGrammar {
                 for g in v.iter_mut() {
                     g.simplify();
                 }
-                // Ensure we have at most one null;
-                if v.iter().any(|g| g.is_null()) {
-                    v.retain(|g| !g.is_null());
-                    v.push(RegularGrammar::Phrase(Vec::new()));
+                // Ensure we have at most one null, keeping it where the
+                // first was, which is when the parser settles for nothing.
+                if let Some(first) = v.iter().position(|g| g.is_null()) {
+                    v[first] = RegularGrammar::Phrase(Vec::new());
+                    let mut i = 0;
+                    v.retain(|g| {
+                        i += 1;
+                        i - 1 == first || !g.is_null()
+                    });
                 }
             }
             RegularGrammar::Many0(g) => {
@@ -96,7 +102,7 @@ impl RegularGrammar {
     fn nullable(&self) -> bool {
         match self {
             RegularGrammar::Word { bytes, .. } => bytes.is_empty(),
-            RegularGrammar::Phrase(v) => v.is_empty(),
+            RegularGrammar::Phrase(v) => v.iter().all(|g| g.nullable()),
             RegularGrammar::Many0(_) => true,
             RegularGrammar::Choice(v) => v.iter().any(|g| g.nullable()),
         }
@@ -153,6 +159,65 @@ impl RegularGrammar {
         // println!("lastpos {self:?}: {out:?}");
         out
     }
+    /// Where a phrase could start if it starts with us and goes on to
+    /// `then`, in the order the parser tries them.
+    fn preferred_firstpos(&self, then: &[Next]) -> Vec<Next> {
+        match self {
+            RegularGrammar::Word { position, .. } => vec![Next::only(*position)],
+            RegularGrammar::Phrase(v) => v
+                .iter()
+                .rev()
+                .fold(then.to_vec(), |then, g| g.preferred_firstpos(&then)),
+            RegularGrammar::Many0(g) => {
+                // Each repeat must eat something.
+                let mut out = g.preferred_firstpos(&[]);
+                push_next(&mut out, then);
+                out
+            }
+            RegularGrammar::Choice(v) => {
+                let mut out = Vec::new();
+                for g in v.iter() {
+                    push_next(&mut out, &g.preferred_firstpos(then));
+                }
+                out
+            }
+        }
+    }
+    /// Like [`RegularGrammar::fill_follow`], but with what can follow each
+    /// position in the order the parser tries them, given that `then`
+    /// comes after us.  Another repeat comes before whatever comes after
+    /// the repeats, however far out they are.
+    fn fill_preferred_follow(&self, then: &[Next], table: &mut Vec<Vec<Next>>) {
+        match self {
+            RegularGrammar::Word { position, bytes } => {
+                let last = *position + bytes.len() - 1;
+                if table.len() <= last {
+                    table.resize(last + 1, Vec::new());
+                }
+                for (i, entry) in table[*position..last].iter_mut().enumerate() {
+                    *entry = vec![Next::only(*position + i + 1)];
+                }
+                table[last] = then.to_vec();
+            }
+            RegularGrammar::Phrase(v) => {
+                let mut then = then.to_vec();
+                for g in v.iter().rev() {
+                    g.fill_preferred_follow(&then, table);
+                    then = g.preferred_firstpos(&then);
+                }
+            }
+            RegularGrammar::Many0(g) => {
+                let mut again = g.preferred_firstpos(&[]);
+                push_next(&mut again, then);
+                g.fill_preferred_follow(&again, table);
+            }
+            RegularGrammar::Choice(v) => {
+                for g in v.iter() {
+                    g.fill_preferred_follow(then, table);
+                }
+            }
+        }
+    }
     fn fill_follow(&self, table: &mut Vec<FollowEntry>) {
         match self {
             RegularGrammar::Word { position, bytes } => {
@@ -220,6 +285,34 @@ impl RegularGrammar {
     }
 }
 
+/// A position that can come next.
+#[derive(Debug, Clone, Copy)]
+struct Next {
+    position: usize,
+    /// Whether there is more than one way through the grammar to get
+    /// there, which the position alone doesn't tell apart.
+    several_ways: bool,
+}
+impl Next {
+    fn only(position: usize) -> Self {
+        Next {
+            position,
+            several_ways: false,
+        }
+    }
+}
+
+/// Add `more` to `out`, after what is already there, noting positions we
+/// can now get to another way.
+fn push_next(out: &mut Vec<Next>, more: &[Next]) {
+    for next in more {
+        match out.iter_mut().find(|n| n.position == next.position) {
+            Some(n) => n.several_ways = true,
+            None => out.push(*next),
+        }
+    }
+}
+
 // fn print_follow_table(table: &Vec<FollowEntry>) {
 //     for (i, e) in table.iter().enumerate() {
 //         println!("{i:2}: {:?} -> {:?}", numchar(e.bytenum), e.followed_by);
@@ -247,6 +340,15 @@ fn numchar(n: usize) -> char {
         _ => panic!("unsupported character"),
     }
 }
+/// What could come next in a phrase.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completions {
+    /// The words that could come next, in order.
+    pub next_words: Vec<String>,
+    /// Whether the phrase could end here.
+    pub can_end: bool,
+}
+
 pub struct State {
     /// The pattern could end here with this prefix.
     complete: bool,
@@ -322,8 +424,164 @@ impl DFA {
     }
 }
 
+impl DFA {
+    /// Whether saying nothing at all is a phrase.  [`DFA::check`] starts
+    /// after the space before the first word, so it never says so.
+    pub(super) fn accepts_nothing(&self) -> bool {
+        self.states.first().is_some_and(|s| s.complete)
+    }
+
+    /// Whether a phrase finishes before the end of `input`, after one of
+    /// its words.
+    pub(super) fn finishes_early(&self, input: &str) -> bool {
+        let mut current_state = 1;
+        for b in input.bytes() {
+            if b == b' ' && self.states[current_state].complete {
+                return true;
+            }
+            match try_charnum(b).map(|n| self.states[current_state].next[n]) {
+                Some(next) if next < self.states.len() => current_state = next,
+                _ => return false,
+            }
+        }
+        false
+    }
+
+    /// Like [`DFA::check`], but saying how many words were fine and which
+    /// words could have come next.
+    pub fn diagnose(&self, input: &str) -> Result<(), ParseError> {
+        let error = match self.check(input) {
+            Ok(()) => return Ok(()),
+            Err(e) => e,
+        };
+        let words: Vec<&str> = input.split(' ').collect();
+        // Before the space that starts the first word.
+        let mut boundary = 0;
+        let mut offset = 0;
+        for word in words.iter() {
+            match self.read_word(boundary, word) {
+                Some(state) if self.at_boundary(state) => {
+                    boundary = state;
+                    offset += 1;
+                }
+                _ => break,
+            }
+        }
+        Err(ParseError {
+            error,
+            offset,
+            after: words[..offset].join(" "),
+            expected: self.words_after(boundary),
+        })
+    }
+
+    /// What could come after the words in `prefix`, or `None` if no phrase
+    /// starts with them.
+    pub fn completions(&self, prefix: &str) -> Option<Completions> {
+        let state = self.state_after(prefix)?;
+        Some(Completions {
+            next_words: self.words_after(state),
+            can_end: self.states[state].complete,
+        })
+    }
+
+    /// Ways to finish a phrase starting with `prefix` in no more than
+    /// `max_words` more words, fewest words first.
+    pub fn example_completions<'a>(
+        &'a self,
+        prefix: &str,
+        max_words: usize,
+    ) -> impl 'a + Iterator<Item = String> {
+        let mut queue: VecDeque<(usize, Vec<String>)> = self
+            .state_after(prefix)
+            .map(|s| (s, Vec::new()))
+            .into_iter()
+            .collect();
+        std::iter::from_fn(move || {
+            while let Some((state, words)) = queue.pop_front() {
+                if words.len() < max_words {
+                    for word in self.words_after(state) {
+                        if let Some(next) = self.read_word(state, &word) {
+                            let mut words = words.clone();
+                            words.push(word);
+                            queue.push_back((next, words));
+                        }
+                    }
+                }
+                if !words.is_empty() && self.states[state].complete {
+                    return Some(words.join(" "));
+                }
+            }
+            None
+        })
+    }
+
+    /// Where we are after reading the whole words of `prefix`.
+    fn state_after(&self, prefix: &str) -> Option<usize> {
+        let mut state = 0;
+        if prefix.is_empty() {
+            return Some(state);
+        }
+        for word in prefix.split(' ') {
+            state = self.read_word(state, word)?;
+            if !self.at_boundary(state) {
+                return None;
+            }
+        }
+        Some(state)
+    }
+
+    fn read_word(&self, state: usize, word: &str) -> Option<usize> {
+        let mut state = self.states[state].next[charnum(b' ')];
+        for b in word.bytes() {
+            state = *self.states.get(state)?.next.get(try_charnum(b)?)?;
+        }
+        self.states.get(state).map(|_| state)
+    }
+
+    /// Whether a word could finish here.
+    fn at_boundary(&self, state: usize) -> bool {
+        let state = &self.states[state];
+        state.complete || state.next[charnum(b' ')] < self.states.len()
+    }
+
+    /// The words that could come next, in order.
+    fn words_after(&self, state: usize) -> Vec<String> {
+        let mut words = Vec::new();
+        let mut stack = vec![(self.states[state].next[charnum(b' ')], String::new())];
+        while let Some((state, word)) = stack.pop() {
+            let Some(here) = self.states.get(state) else {
+                continue;
+            };
+            if !word.is_empty() && self.at_boundary(state) {
+                words.push(word.clone());
+            }
+            // Within a word we only go forward, so this doesn't go on for
+            // ever.
+            for bytenum in 0..26 {
+                if here.next[bytenum] < self.states.len() {
+                    let mut word = word.clone();
+                    word.push(numchar(bytenum));
+                    stack.push((here.next[bytenum], word));
+                }
+            }
+        }
+        words.sort();
+        words.dedup();
+        words
+    }
+}
+
 impl From<RegularGrammar> for DFA {
     fn from(g: RegularGrammar) -> Self {
+        DFA::with_positions(&g).0
+    }
+}
+
+impl DFA {
+    /// The DFA for `g`, along with the positions each of its states could
+    /// be at.
+    fn with_positions(g: &RegularGrammar) -> (Self, Vec<SetUsize>) {
         let mut follow = Vec::new();
         g.fill_follow(&mut follow);
         // println!("\nfinal follow");
@@ -369,22 +627,395 @@ impl From<RegularGrammar> for DFA {
             states[i].complete = sets[i].contains(0);
             // println!("{i:2} == {:?}: {:?}", sets[i], states[i]);
         }
-        DFA { states }
+        (DFA { states }, sets)
+    }
+
+    /// The state after `b` from `state`, if there is one.
+    fn next_state(&self, state: usize, b: u8) -> Option<usize> {
+        let next = self.states[state].next[try_charnum(b)?];
+        (next < self.states.len()).then_some(next)
     }
 }
 
 impl DFA {
-    pub fn encode<P: IsParser>(parser: &P) -> Self {
-        let mut next_position = 1;
-        let grammar = parser.to_grammar(&mut next_position);
-        let mut grammar = RegularGrammar::Phrase(vec![
-            grammar,
-            RegularGrammar::Word {
-                bytes: vec![b'z'],
-                position: 0,
-            },
-        ]);
-        grammar.simplify();
-        grammar.into()
+    pub fn encode<P: IsParser + ?Sized>(parser: &P) -> Self {
+        finished_grammar(parser).into()
+    }
+
+    /// The shortest phrase that we accept and `other` doesn't, if there is
+    /// one.  Checking against an empty [`DFA::default`] gives our shortest
+    /// phrase.
+    pub(super) fn example_not_in(&self, other: &DFA) -> Option<String> {
+        fn state(dfa: &DFA, n: usize) -> Option<&State> {
+            dfa.states.get(n)
+        }
+        // How we first got to each pair of states, by state and byte.
+        let mut came_from = HashMap::new();
+        came_from.insert((0, 0), None);
+        let mut queue = VecDeque::from([(0, 0)]);
+        while let Some((a, b)) = queue.pop_front() {
+            if self.states[a].complete && !state(other, b).is_some_and(|s| s.complete) {
+                let mut bytes = Vec::new();
+                let mut here = (a, b);
+                while let Some((previous, bytenum)) = came_from[&here] {
+                    bytes.push(numchar(bytenum));
+                    here = previous;
+                }
+                return Some(
+                    bytes
+                        .into_iter()
+                        .rev()
+                        .collect::<String>()
+                        .trim()
+                        .to_string(),
+                );
+            }
+            for bytenum in 0..27 {
+                let next_a = self.states[a].next[bytenum];
+                if next_a >= self.states.len() {
+                    continue;
+                }
+                let next_b = state(other, b).map_or(usize::MAX, |s| s.next[bytenum]);
+                let next = (next_a, next_b.min(other.states.len()));
+                if let Entry::Vacant(e) = came_from.entry(next) {
+                    e.insert(Some(((a, b), bytenum)));
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// The grammar for `parser`, followed by a "z" at position 0 to mark where
+/// it is allowed to finish.
+fn finished_grammar<P: IsParser + ?Sized>(parser: &P) -> RegularGrammar {
+    let mut next_position = 1;
+    let grammar = parser.to_grammar(&mut next_position);
+    let mut grammar = RegularGrammar::Phrase(vec![
+        grammar,
+        RegularGrammar::Word {
+            bytes: vec![b'z'],
+            position: 0,
+        },
+    ]);
+    grammar.simplify();
+    grammar
+}
+
+/// Where the words of a grammar are, and which positions can follow which,
+/// for finding the words of the grammar that a phrase is made of.
+pub(super) struct Positions {
+    /// The grammar's [`DFA`], numbered like our positions.
+    pub(super) dfa: DFA,
+    /// The last letters of words that each state of the DFA could be at,
+    /// with their byte numbers.
+    word_ends: Vec<Vec<(usize, usize)>>,
+    /// The positions that can follow each, in order.  The last entry is for
+    /// before the phrase starts.
+    follow: Vec<Vec<usize>>,
+    /// The same, in the order the parser tries them.
+    preferred: Vec<Vec<Next>>,
+}
+
+impl Positions {
+    pub(super) fn encode<P: IsParser + ?Sized>(parser: &P) -> Self {
+        let grammar = finished_grammar(parser);
+        let mut table = Vec::new();
+        grammar.fill_follow(&mut table);
+        table.push(FollowEntry {
+            bytenum: 27,
+            followed_by: grammar.firstpos(),
+        });
+        let (dfa, sets) = DFA::with_positions(&grammar);
+        // A letter followed only by spaces, or by the end of the phrase,
+        // finishes its word.
+        let ends_word = |p: usize| {
+            p != 0
+                && table[p].bytenum != 26
+                && table[p]
+                    .followed_by
+                    .iter()
+                    .all(|q| q == 0 || table[q].bytenum == 26)
+        };
+        let word_ends = sets
+            .iter()
+            .map(|set| {
+                let mut ends: Vec<(usize, usize)> = set
+                    .iter()
+                    .filter(|&p| ends_word(p))
+                    .map(|p| (table[p].bytenum, p))
+                    .collect();
+                ends.sort_unstable();
+                ends
+            })
+            .collect();
+        let follow = table
+            .iter()
+            .map(|entry| {
+                let mut next: Vec<usize> = entry.followed_by.iter().collect();
+                next.sort_unstable();
+                next
+            })
+            .collect();
+        let mut preferred = Vec::new();
+        grammar.fill_preferred_follow(&[], &mut preferred);
+        preferred.resize(table.len() - 1, Vec::new());
+        preferred.push(grammar.preferred_firstpos(&[]));
+        Positions {
+            dfa,
+            word_ends,
+            follow,
+            preferred,
+        }
+    }
+
+    fn follows(&self, p: usize, q: usize) -> bool {
+        self.follow[p].binary_search(&q).is_ok()
+    }
+
+    /// The position of the space before each word of `input`, along the
+    /// way the parser would read all of it, if it can.
+    ///
+    /// The parser tries the first option of a choice, another repeat and
+    /// something rather than nothing first, and where the phrase can go
+    /// from a position depends only on the position.  So where there is
+    /// more than one way, the parser takes the first way on from each word
+    /// that can still finish the phrase.  If that way is more than one way
+    /// through the grammar, though, the positions can't say which the
+    /// parser took, so we give up.
+    pub(super) fn path(&self, input: &str) -> Option<Vec<usize>> {
+        self.only_path(input)
+            .unwrap_or_else(|| self.preferred_path(input))
+    }
+
+    /// The path for `input` when each of its words can only start in one
+    /// place, as they mostly do, so there is nothing to choose.  `None` if
+    /// we have to choose.
+    fn only_path(&self, input: &str) -> Option<Option<Vec<usize>>> {
+        let mut path = Vec::new();
+        let mut p = self.follow.len() - 1;
+        let mut state = 0;
+        for word in input.split(' ') {
+            let Some((after, mut starts)) = self.read_word(state, word) else {
+                return Some(None);
+            };
+            let (Some(q), None) = (starts.next(), starts.next()) else {
+                return None;
+            };
+            let next = self.preferred[p].iter().find(|next| next.position == q)?;
+            if next.several_ways {
+                return Some(None);
+            }
+            path.push(q);
+            p = q + word.len();
+            state = after;
+        }
+        // Every way through the phrase goes this way, so if any can finish,
+        // this one does.
+        if !self.dfa.states[state].complete {
+            return Some(None);
+        }
+        Some(self.finish_one_way(p).then_some(path))
+    }
+
+    /// The path for `input`, choosing the way the parser would where its
+    /// words could start in more than one place.
+    fn preferred_path(&self, input: &str) -> Option<Vec<usize>> {
+        // Where each word could start, given the words before it, with the
+        // length of the word.
+        let mut layers: Vec<(usize, Vec<usize>)> = Vec::new();
+        let mut state = 0;
+        for word in input.split(' ') {
+            let (after, starts) = self.read_word(state, word)?;
+            let layer: Vec<usize> = starts.collect();
+            if layer.is_empty() {
+                return None;
+            }
+            layers.push((word.len(), layer));
+            state = after;
+        }
+        if !self.dfa.states[state].complete {
+            return None;
+        }
+
+        // Keep just the starts that can go on to finish the phrase, at
+        // position 0.
+        for i in (0..layers.len()).rev() {
+            let (layer, after) = layers.split_at_mut(i + 1);
+            let next = after.first().map_or(&[0][..], |(_, next)| next);
+            let (len, layer) = &mut layer[i];
+            layer.retain(|&q| next.iter().any(|&n| self.follows(q + *len, n)));
+            if layer.is_empty() {
+                return None;
+            }
+        }
+
+        let mut path = Vec::with_capacity(layers.len());
+        let mut p = self.follow.len() - 1;
+        for (len, layer) in layers.iter() {
+            let q = self.preferred[p]
+                .iter()
+                .find(|q| layer.contains(&q.position))?;
+            if q.several_ways {
+                return None;
+            }
+            path.push(q.position);
+            p = q.position + len;
+        }
+        self.finish_one_way(p).then_some(path)
+    }
+
+    /// Read `word` with the DFA from `state`, giving the state after it and
+    /// where the word could have started.  The DFA reads up to the last
+    /// letter of the word, and the words it could be reading are the ones
+    /// that end with that letter there.
+    fn read_word<'a>(
+        &'a self,
+        state: usize,
+        word: &'a str,
+    ) -> Option<(usize, impl Iterator<Item = usize> + 'a)> {
+        let (last, front) = word.as_bytes().split_last()?;
+        let mut state = self.dfa.next_state(state, b' ')?;
+        for b in front.iter().copied() {
+            state = self.dfa.next_state(state, b)?;
+        }
+        let bytenum = try_charnum(*last)?;
+        let starts = self.word_ends[state]
+            .iter()
+            .filter(move |(b, _)| *b == bytenum)
+            .map(move |(_, p)| p - word.len());
+        Some((self.dfa.next_state(state, *last)?, starts))
+    }
+
+    /// Whether the phrase can finish after position `p` in just one way.
+    fn finish_one_way(&self, p: usize) -> bool {
+        // Finishing is usually the last thing the parser tries.
+        self.preferred[p]
+            .iter()
+            .rev()
+            .find(|q| q.position == 0)
+            .is_some_and(|finish| !finish.several_ways)
+    }
+}
+
+/// Whole phrases that `parser` can read in more than one way, the shortest
+/// for each place where two readings part.
+///
+/// Each byte of each word in the grammar has its own position, and reading
+/// a phrase is a path through the positions, so two readings of a phrase are
+/// two paths through the follow table that spell the same bytes.  We walk
+/// pairs of paths that have parted, and report the phrases where both can
+/// finish.
+pub(super) fn ambiguous_phrases<P: IsParser + ?Sized>(parser: &P) -> Vec<String> {
+    let grammar = finished_grammar(parser);
+    let mut follow = Vec::new();
+    grammar.fill_follow(&mut follow);
+    // A position before the phrase starts.
+    let start = follow.len();
+    follow.push(FollowEntry {
+        bytenum: 27,
+        followed_by: grammar.firstpos(),
+    });
+
+    // The quickest way to each position, for the start of our examples.
+    let mut came_from = vec![None; follow.len()];
+    let mut order = vec![start];
+    let mut i = 0;
+    while i < order.len() {
+        let p = order[i];
+        for next in follow[p].followed_by.iter() {
+            if came_from[next].is_none() {
+                came_from[next] = Some(p);
+                order.push(next);
+            }
+        }
+        i += 1;
+    }
+
+    // Where readings part: two positions with the same byte after the same
+    // position.
+    let mut partings = Vec::new();
+    let mut pairs = HashMap::new();
+    let mut queue = VecDeque::new();
+    for &p in order.iter() {
+        let next: Vec<usize> = follow[p].followed_by.iter().collect();
+        for (j, &a) in next.iter().enumerate() {
+            for &b in next[j + 1..].iter() {
+                let parted = (a.min(b), a.max(b));
+                if follow[a].bytenum == follow[b].bytenum && !pairs.contains_key(&parted) {
+                    pairs.insert(parted, pairs.len());
+                    partings.push((p, parted));
+                    queue.push_back(parted);
+                }
+            }
+        }
+    }
+
+    // Follow the parted readings along together, remembering how we got
+    // to each pair so that we can find the way back from the finish.
+    let mut states: Vec<(usize, usize)> = queue.iter().copied().collect();
+    let mut came_into: Vec<Vec<usize>> = vec![Vec::new(); states.len()];
+    while let Some((a, b)) = queue.pop_front() {
+        let here = pairs[&(a, b)];
+        for next_a in follow[a].followed_by.iter() {
+            for next_b in follow[b].followed_by.iter() {
+                if follow[next_a].bytenum != follow[next_b].bytenum {
+                    continue;
+                }
+                let next = (next_a.min(next_b), next_a.max(next_b));
+                let there = match pairs.get(&next) {
+                    Some(&there) => there,
+                    None => {
+                        pairs.insert(next, states.len());
+                        states.push(next);
+                        came_into.push(Vec::new());
+                        queue.push_back(next);
+                        states.len() - 1
+                    }
+                };
+                came_into[there].push(here);
+            }
+        }
+    }
+
+    // The quickest way from each pair to where both readings finish.
+    let mut towards_finish = vec![None; states.len()];
+    if let Some(&finish) = pairs.get(&(0, 0)) {
+        towards_finish[finish] = Some(finish);
+        let mut queue = VecDeque::from([finish]);
+        while let Some(there) = queue.pop_front() {
+            for &here in came_into[there].iter() {
+                if towards_finish[here].is_none() {
+                    towards_finish[here] = Some(there);
+                    queue.push_back(here);
+                }
+            }
+        }
+    }
+
+    let mut phrases = Vec::new();
+    for (before, parted) in partings {
+        let mut here = pairs[&parted];
+        if towards_finish[here].is_none() {
+            continue;
+        }
+        let mut bytes = Vec::new();
+        let mut p = before;
+        while let Some(previous) = came_from[p] {
+            bytes.push(numchar(follow[p].bytenum));
+            p = previous;
+        }
+        bytes.reverse();
+        while states[here] != (0, 0) {
+            bytes.push(numchar(follow[states[here].0].bytenum));
+            here = towards_finish[here].unwrap();
+        }
+        let phrase = bytes.into_iter().collect::<String>().trim().to_string();
+        if !phrases.contains(&phrase) {
+            phrases.push(phrase);
+        }
     }
+    phrases.sort_by(|a, b| (a.len(), a).cmp(&(b.len(), b)));
+    phrases
 }
@@ -0,0 +1,404 @@
+//! Deciding when we are being spoken to with a key on the keyboard, as an
+//! alternative to (or in addition to) listening for pauses.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rdev::{EventType, Key};
+
+use crate::events::{Event, Events};
+use crate::vad::{PhraseSegmenter, SegmentEvent, Segmenter, Utterance, VadConfig};
+
+/// How we split the audio stream into utterances.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segmentation {
+    /// Listen all the time, with phrases separated by pauses.
+    Vad(VadConfig),
+    /// An utterance is whatever is said while the key is held down, unless
+    /// it goes on for longer than the given time, in which case we throw it
+    /// away, as for [`VadConfig::max_utterance`].
+    PushToTalk(Key, Duration),
+    /// Pressing the key turns listening on and off, e.g. for the duration of
+    /// a meeting.  While on, phrases are separated by pauses as for
+    /// [`Segmentation::Vad`].
+    Toggle(Key, VadConfig),
+}
+
+impl Default for Segmentation {
+    fn default() -> Self {
+        Segmentation::Vad(VadConfig::default())
+    }
+}
+
+impl Segmentation {
+    /// Create the segmenter, listening to the keyboard in the background if
+    /// need be, and telling `events` what the keyboard did.
+    pub fn segmenter(&self, events: &Events) -> Box<dyn Segmenter> {
+        match self {
+            Segmentation::Vad(config) => Box::new(PhraseSegmenter::new(config)),
+            Segmentation::PushToTalk(key, max_utterance) => Box::new(HeldKeySegmenter::new(
+                KeySwitch::hold(*key, events),
+                *max_utterance,
+            )),
+            Segmentation::Toggle(key, config) => Box::new(GatedSegmenter::new(
+                KeySwitch::toggle(*key, events),
+                PhraseSegmenter::new(config),
+            )),
+        }
+    }
+}
+
+/// Keys that make sense for push-to-talk, because they don't do much else.
+const SWITCH_KEYS: &[Key] = &[
+    Key::F1,
+    Key::F2,
+    Key::F3,
+    Key::F4,
+    Key::F5,
+    Key::F6,
+    Key::F7,
+    Key::F8,
+    Key::F9,
+    Key::F10,
+    Key::F11,
+    Key::F12,
+    Key::ScrollLock,
+    Key::Pause,
+    Key::PrintScreen,
+    Key::Insert,
+    Key::CapsLock,
+    Key::NumLock,
+    Key::ControlLeft,
+    Key::ControlRight,
+    Key::Alt,
+    Key::AltGr,
+    Key::ShiftLeft,
+    Key::ShiftRight,
+    Key::MetaLeft,
+    Key::MetaRight,
+    Key::Function,
+];
+
+/// Look up a key by its `rdev` name, ignoring case, e.g. `F12` or
+/// `scrolllock`.
+pub fn parse_key(name: &str) -> anyhow::Result<Key> {
+    SWITCH_KEYS
+        .iter()
+        .copied()
+        .find(|k| format!("{k:?}").eq_ignore_ascii_case(name))
+        .ok_or_else(|| {
+            let names: Vec<String> = SWITCH_KEYS.iter().map(|k| format!("{k:?}")).collect();
+            anyhow::anyhow!(
+                "{name:?} is not a key we can listen to.  Try one of: {}",
+                names.join(", ")
+            )
+        })
+}
+
+/// An on/off switch that is flipped by a key on the keyboard.
+#[derive(Debug, Clone, Default)]
+pub struct KeySwitch(Arc<AtomicBool>);
+
+impl KeySwitch {
+    /// A switch that is on while `key` is held down.
+    pub fn hold(key: Key, events: &Events) -> Self {
+        let switch = KeySwitch::default();
+        let s = switch.clone();
+        watch_key(key, events, move |pressed| s.set(pressed));
+        switch
+    }
+
+    /// A switch that starts out on, and is flipped each time `key` is
+    /// pressed.
+    pub fn toggle(key: Key, events: &Events) -> Self {
+        let switch = KeySwitch::default();
+        switch.set(true);
+        let s = switch.clone();
+        let mut was_pressed = false;
+        let toggled = events.clone();
+        watch_key(key, events, move |pressed| {
+            // Ignore the repeats we get while the key is held down.
+            if pressed && !was_pressed {
+                s.set(!s.is_on());
+                toggled.emit(Event::ListeningToggled { on: s.is_on() });
+            }
+            was_pressed = pressed;
+        });
+        switch
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub fn set(&self, on: bool) {
+        self.0.store(on, Ordering::Relaxed)
+    }
+}
+
+/// Call `f` with whether `key` is down every time it is pressed or released.
+fn watch_key(key: Key, events: &Events, mut f: impl 'static + Send + FnMut(bool)) {
+    let events = events.clone();
+    std::thread::spawn(move || {
+        // This will block.
+        if let Err(error) = rdev::listen(move |event| match event.event_type {
+            EventType::KeyPress(k) if k == key => f(true),
+            EventType::KeyRelease(k) if k == key => f(false),
+            _ => (),
+        }) {
+            events.emit(Event::Error {
+                message: format!("Unable to listen to the keyboard: {error:?}"),
+            });
+        }
+    });
+}
+
+/// Treats everything heard while a switch is on as one utterance.
+pub struct HeldKeySegmenter {
+    switch: KeySwitch,
+    max_samples: usize,
+    /// How many samples we have seen so far.
+    position: usize,
+    /// When the current utterance began, and what we have of it.
+    current: Option<(usize, Vec<i16>)>,
+    /// Whether we threw away what we heard, and are waiting for the switch
+    /// to be turned off, e.g. because we missed the key being released.
+    abandoned: bool,
+}
+
+impl HeldKeySegmenter {
+    pub fn new(switch: KeySwitch, max_utterance: Duration) -> Self {
+        HeldKeySegmenter {
+            switch,
+            max_samples: crate::vad::duration_to_samples(max_utterance),
+            position: 0,
+            current: None,
+            abandoned: false,
+        }
+    }
+}
+
+impl Segmenter for HeldKeySegmenter {
+    fn push(&mut self, samples: &[i16]) -> Vec<SegmentEvent> {
+        let mut events = Vec::new();
+        if !self.switch.is_on() {
+            self.abandoned = false;
+            events.extend(self.flush());
+        } else if !self.abandoned {
+            let (start, utterance) = self.current.get_or_insert_with(|| {
+                events.push(SegmentEvent::Started {
+                    start: crate::vad::samples_to_duration(self.position),
+                });
+                (self.position, Vec::new())
+            });
+            utterance.extend_from_slice(samples);
+            if utterance.len() >= self.max_samples {
+                events.push(SegmentEvent::Abandoned {
+                    start: crate::vad::samples_to_duration(*start),
+                    end: crate::vad::samples_to_duration(*start + utterance.len()),
+                });
+                self.current = None;
+                self.abandoned = true;
+            }
+        }
+        self.position += samples.len();
+        events
+    }
+
+    fn flush(&mut self) -> Option<SegmentEvent> {
+        let (start, samples) = self.current.take()?;
+        Some(SegmentEvent::Ended(Utterance {
+            start: crate::vad::samples_to_duration(start),
+            end: crate::vad::samples_to_duration(start + samples.len()),
+            samples,
+        }))
+    }
+
+    fn reset(&mut self) {
+        self.current = None;
+    }
+
+    fn skip(&mut self, samples: usize) {
+        self.reset();
+        self.position += samples;
+    }
+
+    fn current(&self) -> Option<&[i16]> {
+        self.current.as_ref().map(|(_, samples)| &samples[..])
+    }
+}
+
+/// Only passes on what another segmenter hears while a switch is on.
+pub struct GatedSegmenter<S> {
+    switch: KeySwitch,
+    inner: S,
+    was_on: bool,
+}
+
+impl<S: Segmenter> GatedSegmenter<S> {
+    pub fn new(switch: KeySwitch, inner: S) -> Self {
+        GatedSegmenter {
+            was_on: switch.is_on(),
+            switch,
+            inner,
+        }
+    }
+}
+
+impl<S: Segmenter> Segmenter for GatedSegmenter<S> {
+    fn push(&mut self, samples: &[i16]) -> Vec<SegmentEvent> {
+        let is_on = self.switch.is_on();
+        let mut events = Vec::new();
+        if self.was_on && !is_on {
+            // Whatever was said before the switch was turned off still counts.
+            events.extend(self.inner.flush());
+        }
+        self.was_on = is_on;
+        if is_on {
+            events.extend(self.inner.push(samples));
+        } else {
+            // Keep the inner segmenter's clock running, but don't let it hear
+            // anything.
+            self.inner.skip(samples.len());
+        }
+        events
+    }
+
+    fn flush(&mut self) -> Option<SegmentEvent> {
+        self.inner.flush()
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset()
+    }
+
+    fn skip(&mut self, samples: usize) {
+        self.inner.skip(samples)
+    }
+
+    fn current(&self) -> Option<&[i16]> {
+        self.inner.current()
+    }
+}
+
+#[test]
+fn parse_key_names() {
+    assert_eq!(Key::F12, parse_key("F12").unwrap());
+    assert_eq!(Key::ScrollLock, parse_key("scrolllock").unwrap());
+    assert_eq!(Key::ControlRight, parse_key("controlright").unwrap());
+    let e = parse_key("banana").unwrap_err();
+    assert!(format!("{e}").contains("ScrollLock"), "{e}");
+}
+
+#[test]
+fn push_to_talk() {
+    let switch = KeySwitch::default();
+    let mut segmenter = HeldKeySegmenter::new(switch.clone(), VadConfig::default().max_utterance);
+    let ms = std::time::Duration::from_millis;
+    assert!(segmenter.push(&[0; 160]).is_empty());
+    switch.set(true);
+    assert_eq!(
+        vec![SegmentEvent::Started { start: ms(10) }],
+        segmenter.push(&[1; 160])
+    );
+    assert!(segmenter.push(&[2; 320]).is_empty());
+    switch.set(false);
+    let events = segmenter.push(&[0; 160]);
+    assert_eq!(1, events.len());
+    if let SegmentEvent::Ended(u) = &events[0] {
+        assert_eq!(u.start, ms(10));
+        assert_eq!(u.end, ms(40));
+        assert_eq!(&u.samples[..160], &[1; 160]);
+        assert_eq!(&u.samples[160..], &[2; 320]);
+    } else {
+        panic!("expected the end of an utterance, not {:?}", events[0]);
+    }
+    assert!(segmenter.push(&[3; 160]).is_empty());
+
+    // Audio that runs out while the key is held still counts.
+    switch.set(true);
+    assert_eq!(1, segmenter.push(&[4; 160]).len());
+    assert!(matches!(segmenter.flush(), Some(SegmentEvent::Ended(u)) if u.samples == [4; 160]));
+}
+
+#[test]
+fn toggled_listening() {
+    let switch = KeySwitch::default();
+    switch.set(true);
+    let loud = |frame: &[i16]| frame.iter().any(|&s| s != 0);
+    let inner = PhraseSegmenter::with_detector(&VadConfig::default(), loud);
+    let mut segmenter = GatedSegmenter::new(switch.clone(), inner);
+    let ms = std::time::Duration::from_millis;
+
+    assert_eq!(
+        vec![SegmentEvent::Started { start: ms(0) }],
+        segmenter.push(&[1; 4800])
+    );
+    // Turning listening off ends the phrase in progress.
+    switch.set(false);
+    let events = segmenter.push(&[1; 4800]);
+    assert!(matches!(&events[..], [SegmentEvent::Ended(u)] if u.samples == [1; 4800]));
+    assert!(segmenter.push(&[1; 4800]).is_empty());
+    assert_eq!(None, segmenter.flush());
+
+    // Turning it back on, we hear speech, with the right timestamp (less
+    // the pre-roll).
+    switch.set(true);
+    assert!(segmenter.push(&[0; 4800]).is_empty());
+    assert_eq!(
+        vec![SegmentEvent::Started {
+            start: ms(1200 - 250)
+        }],
+        segmenter.push(&[1; 4800])
+    );
+}
+
+#[test]
+fn toggled_listening_keeps_time() {
+    let switch = KeySwitch::default();
+    let loud = |frame: &[i16]| frame.iter().any(|&s| s != 0);
+    let inner = PhraseSegmenter::with_detector(&VadConfig::default(), loud);
+    let mut segmenter = GatedSegmenter::new(switch.clone(), inner);
+
+    // Chunks that don't fit neatly into 30 ms frames.
+    for _ in 0..10 {
+        assert!(segmenter.push(&[1; 1024]).is_empty());
+    }
+    switch.set(true);
+    assert!(segmenter.push(&[0; 1024]).is_empty());
+    assert_eq!(
+        vec![SegmentEvent::Started {
+            start: std::time::Duration::from_millis(640)
+        }],
+        segmenter.push(&[1; 1024])
+    );
+}
+
+#[test]
+fn push_to_talk_gives_up_eventually() {
+    let switch = KeySwitch::default();
+    let ms = std::time::Duration::from_millis;
+    let mut segmenter = HeldKeySegmenter::new(switch.clone(), ms(50));
+    switch.set(true);
+    assert_eq!(1, segmenter.push(&[1; 480]).len());
+    assert_eq!(
+        vec![SegmentEvent::Abandoned {
+            start: ms(0),
+            end: ms(60)
+        }],
+        segmenter.push(&[1; 480])
+    );
+    // We missed the key being released, so we wait for it to be pressed
+    // again.
+    assert!(segmenter.push(&[1; 480]).is_empty());
+    assert_eq!(None, segmenter.current());
+    assert_eq!(None, segmenter.flush());
+    switch.set(false);
+    assert!(segmenter.push(&[0; 480]).is_empty());
+    switch.set(true);
+    assert_eq!(
+        vec![SegmentEvent::Started { start: ms(120) }],
+        segmenter.push(&[1; 480])
+    );
+}
@@ -0,0 +1,184 @@
+//! Everything we made of an utterance: what the recognizer thought was said,
+//! what each guess meant to the grammar, and what we decided to do.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::choice::{choose, ChoiceConfig};
+use crate::parser::{Error, IsParser};
+use crate::recognizer::Transcript;
+
+/// What the grammar made of one transcript.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// The whole transcript is a command.
+    Complete,
+    /// The transcript is the start of a command, but only the start.
+    Incomplete,
+    /// The transcript isn't a command.
+    Wrong,
+    /// The transcript starts with a command, followed by these words.
+    ExtraWords(String),
+}
+
+impl Outcome {
+    pub fn is_complete(&self) -> bool {
+        *self == Outcome::Complete
+    }
+}
+
+/// One of the recognizer's guesses, and what it would have meant.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Hypothesis {
+    pub text: String,
+    pub confidence: f64,
+    pub outcome: Outcome,
+}
+
+/// How long things took.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timing {
+    /// The length of the utterance.
+    pub audio: Duration,
+    /// How long we spent waiting for the recognizer, once the utterance
+    /// was over.
+    pub recognizing: Duration,
+}
+
+/// What we heard in an utterance, and what we decided it meant.
+#[derive(Debug)]
+pub struct Recognition<T> {
+    /// Every guess the recognizer made, most confident first.
+    pub hypotheses: Vec<Hypothesis>,
+    /// The index of the hypothesis we went with.
+    pub chosen: Option<usize>,
+    /// Whether another command came within the margin of the one we chose.
+    pub ambiguous: bool,
+    /// What the chosen hypothesis means.
+    pub action: Option<T>,
+    pub timing: Timing,
+}
+
+impl<T> Recognition<T> {
+    /// Make sense of the recognizer's `transcripts` with `grammar`, picking
+    /// one as `config` says.
+    pub fn new(
+        mut transcripts: Vec<Transcript>,
+        grammar: &impl IsParser<Output = T>,
+        config: &ChoiceConfig,
+        timing: Timing,
+    ) -> Self {
+        transcripts.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+        let mut values = Vec::with_capacity(transcripts.len());
+        let hypotheses: Vec<Hypothesis> = transcripts
+            .into_iter()
+            .map(|t| {
+                let outcome = match grammar.parse(&t.text) {
+                    Ok((value, "")) => {
+                        values.push(Some(value));
+                        Outcome::Complete
+                    }
+                    Ok((_, extra)) => {
+                        values.push(None);
+                        Outcome::ExtraWords(extra.trim().to_string())
+                    }
+                    Err(e) => {
+                        values.push(None);
+                        match e {
+                            Error::Incomplete => Outcome::Incomplete,
+                            Error::Wrong => Outcome::Wrong,
+                        }
+                    }
+                };
+                Hypothesis {
+                    text: t.text,
+                    confidence: t.confidence,
+                    outcome,
+                }
+            })
+            .collect();
+        let (chosen, ambiguous) = choose(&hypotheses, config);
+        let action = chosen.and_then(|i| values[i].take());
+        Recognition {
+            hypotheses,
+            chosen,
+            ambiguous,
+            action,
+            timing,
+        }
+    }
+
+    /// The hypothesis we went with.
+    pub fn chosen(&self) -> Option<&Hypothesis> {
+        self.hypotheses.get(self.chosen?)
+    }
+
+    /// Whether we heard anything at all.
+    pub fn heard_anything(&self) -> bool {
+        self.hypotheses.iter().any(|h| !h.text.is_empty())
+    }
+
+    /// The words after the command in the most confident hypothesis, if it
+    /// started with a command but didn't stop there.
+    pub fn leftover(&self) -> Option<&str> {
+        match &self.hypotheses.first()?.outcome {
+            Outcome::ExtraWords(extra) => Some(extra),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn outcome_of_each_hypothesis() {
+    use crate::parser::IntoParser;
+
+    let grammar = "testing".many1().map(|t| t.len());
+    let transcripts = vec![
+        Transcript::new("testing testing one two", -1.0),
+        Transcript::new("testing testing", -2.0),
+        Transcript::new("", -2.5),
+        Transcript::new("resting", -3.0),
+        Transcript::new("testing", -1.5),
+    ];
+    let timing = Timing {
+        audio: Duration::from_secs(2),
+        recognizing: Duration::from_millis(30),
+    };
+    let recognition = Recognition::new(transcripts, &grammar, &ChoiceConfig::default(), timing);
+    let outcomes: Vec<_> = recognition
+        .hypotheses
+        .iter()
+        .map(|h| (h.text.as_str(), h.outcome.clone()))
+        .collect();
+    assert_eq!(
+        outcomes,
+        [
+            (
+                "testing testing one two",
+                Outcome::ExtraWords("one two".to_string())
+            ),
+            ("testing", Outcome::Complete),
+            ("testing testing", Outcome::Complete),
+            ("", Outcome::Incomplete),
+            ("resting", Outcome::Wrong),
+        ]
+    );
+    assert_eq!(recognition.leftover(), Some("one two"));
+    assert_eq!(recognition.chosen().unwrap().text, "testing");
+    assert!(recognition.ambiguous);
+    assert_eq!(recognition.action, Some(1));
+    assert_eq!(recognition.timing, timing);
+    assert!(recognition.heard_anything());
+
+    let silence = Recognition::new(
+        vec![Transcript::new("", -1.0)],
+        &grammar,
+        &ChoiceConfig::default(),
+        Timing::default(),
+    );
+    assert!(!silence.heard_anything());
+    assert_eq!(silence.chosen, None);
+    assert_eq!(silence.action, None);
+}
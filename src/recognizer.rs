@@ -0,0 +1,338 @@
+//! Turning speech into text, and where to find the speech model.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::Context;
+
+use crate::parser::Error;
+
+#[cfg(feature = "coqui")]
+mod coqui;
+mod fake;
+
+#[cfg(feature = "coqui")]
+pub use coqui::Coqui;
+pub use fake::{hash_samples, FakeRecognizer};
+
+/// Checks whether a phrase fits the grammar we are listening for.
+pub type Checker = Arc<dyn Fn(&str) -> Result<(), Error> + Send + Sync>;
+
+/// One guess at what was said.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transcript {
+    pub text: String,
+    /// How sure the recognizer is, on a scale of its own choosing where
+    /// larger is better.
+    pub confidence: f64,
+}
+
+impl Transcript {
+    pub fn new(text: impl Into<String>, confidence: f64) -> Self {
+        Transcript {
+            text: text.into(),
+            confidence,
+        }
+    }
+}
+
+/// Something that turns 16 kHz mono audio into text.
+pub trait SpeechRecognizer {
+    /// Steer recognition towards phrases that pass `checker`.  Backends that
+    /// can't be steered may ignore it.
+    fn set_checker(&mut self, _checker: Checker) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Guess what was said in `audio`, best guess first.  No guesses means
+    /// nothing was said.
+    fn recognize(&mut self, audio: &[i16]) -> anyhow::Result<Vec<Transcript>>;
+
+    /// Start recognizing an utterance that is still being spoken.
+    ///
+    /// By default we just hold on to the audio until the end.
+    fn stream(&mut self) -> anyhow::Result<Box<dyn UtteranceStream + '_>> {
+        Ok(Box::new(Buffered {
+            recognizer: self,
+            audio: Vec::new(),
+        }))
+    }
+}
+
+/// An utterance being recognized as it arrives.
+pub trait UtteranceStream {
+    fn feed(&mut self, audio: &[i16]);
+    /// The best guess so far, if the recognizer can tell us.
+    fn intermediate(&mut self) -> anyhow::Result<Option<String>>;
+    /// Finish up, with the same result as [`SpeechRecognizer::recognize`].
+    fn finish(self: Box<Self>) -> anyhow::Result<Vec<Transcript>>;
+}
+
+struct Buffered<'a, R: ?Sized> {
+    recognizer: &'a mut R,
+    audio: Vec<i16>,
+}
+
+impl<R: SpeechRecognizer + ?Sized> UtteranceStream for Buffered<'_, R> {
+    fn feed(&mut self, audio: &[i16]) {
+        self.audio.extend_from_slice(audio);
+    }
+
+    fn intermediate(&mut self) -> anyhow::Result<Option<String>> {
+        Ok(None)
+    }
+
+    fn finish(self: Box<Self>) -> anyhow::Result<Vec<Transcript>> {
+        self.recognizer.recognize(&self.audio)
+    }
+}
+
+/// What the model file in a model directory is called.
+const MODEL_FILE: &str = "model.tflite";
+/// The scorer we use if a model directory has more than one.
+const PREFERRED_SCORER: &str = "huge-vocabulary.scorer";
+/// Our directory within each of the XDG data directories.
+const DATA_DIR: &str = "voice-control";
+
+/// How to load and run the Coqui speech model.
+///
+/// [`RecognizerConfig::find`] looks in the environment variables
+/// `VOICE_CONTROL_MODEL` (a model file, or a directory holding `model.tflite`
+/// and a `.scorer`), `VOICE_CONTROL_SCORER` (empty for none),
+/// `VOICE_CONTROL_BEAM_WIDTH`, `VOICE_CONTROL_GUESSES` and
+/// `VOICE_CONTROL_PENALTY`.  Without `VOICE_CONTROL_MODEL` it searches
+/// `voice-control` in `$XDG_DATA_HOME` and `$XDG_DATA_DIRS`, and finally the
+/// `english` directory of a checkout of this repository.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecognizerConfig {
+    /// The acoustic model, e.g. `model.tflite`.
+    pub model: PathBuf,
+    /// The external scorer (language model), if any.
+    pub scorer: Option<PathBuf>,
+    /// How many hypotheses the decoder keeps around, or `None` to leave it
+    /// up to the model.
+    pub beam_width: Option<u32>,
+    /// How many of its best guesses we ask the model for.
+    pub num_guesses: u32,
+    /// How heavily the callback scorer penalizes phrases that don't fit our
+    /// grammar.
+    pub penalty: f64,
+}
+
+impl RecognizerConfig {
+    /// Use the model at `model`, with no scorer and default settings.
+    pub fn new(model: impl Into<PathBuf>) -> Self {
+        RecognizerConfig {
+            model: model.into(),
+            scorer: None,
+            beam_width: None,
+            num_guesses: 16,
+            penalty: 10.0,
+        }
+    }
+
+    /// Use a model file, or the model and scorer in a model directory.
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        if path.is_dir() {
+            let model = path.join(MODEL_FILE);
+            if !model.is_file() {
+                anyhow::bail!("There is no {MODEL_FILE} in {path:?}");
+            }
+            let mut config = RecognizerConfig::new(model);
+            config.scorer = find_scorer(path)?;
+            Ok(config)
+        } else if path.is_file() {
+            Ok(RecognizerConfig::new(path))
+        } else {
+            anyhow::bail!("There is no speech model at {path:?}")
+        }
+    }
+
+    /// Find the model as described above, taking settings from the
+    /// environment.
+    pub fn find() -> anyhow::Result<Self> {
+        RecognizerConfig::find_with(&|name| std::env::var(name).ok())
+    }
+
+    /// Override our settings with any that are given in the environment.
+    pub fn with_env_overrides(self) -> anyhow::Result<Self> {
+        self.with_overrides(&|name| std::env::var(name).ok())
+    }
+
+    fn find_with(env: &dyn Fn(&str) -> Option<String>) -> anyhow::Result<Self> {
+        let config = if let Some(path) = env("VOICE_CONTROL_MODEL") {
+            RecognizerConfig::open(&path).with_context(|| format!("VOICE_CONTROL_MODEL={path}"))?
+        } else {
+            let dirs = search_path(env);
+            if let Some(dir) = dirs.iter().find(|d| d.join(MODEL_FILE).is_file()) {
+                RecognizerConfig::open(dir)?
+            } else {
+                let dirs: Vec<String> = dirs.iter().map(|d| d.display().to_string()).collect();
+                anyhow::bail!(
+                    "Unable to find {MODEL_FILE} in any of\n  {}\nPlease set VOICE_CONTROL_MODEL to say where it is.",
+                    dirs.join("\n  ")
+                );
+            }
+        };
+        config.with_overrides(env)
+    }
+
+    fn with_overrides(mut self, env: &dyn Fn(&str) -> Option<String>) -> anyhow::Result<Self> {
+        if let Some(scorer) = env("VOICE_CONTROL_SCORER") {
+            self.scorer = if scorer.is_empty() {
+                None
+            } else {
+                Some(scorer.into())
+            };
+        }
+        if let Some(v) = env("VOICE_CONTROL_BEAM_WIDTH") {
+            self.beam_width = Some(parse_var("VOICE_CONTROL_BEAM_WIDTH", &v)?);
+        }
+        if let Some(v) = env("VOICE_CONTROL_GUESSES") {
+            self.num_guesses = parse_var("VOICE_CONTROL_GUESSES", &v)?;
+        }
+        if let Some(v) = env("VOICE_CONTROL_PENALTY") {
+            self.penalty = parse_var("VOICE_CONTROL_PENALTY", &v)?;
+        }
+        Ok(self)
+    }
+}
+
+fn parse_var<T: std::str::FromStr>(name: &str, value: &str) -> anyhow::Result<T>
+where
+    T::Err: std::fmt::Display,
+{
+    value
+        .parse()
+        .map_err(|e| anyhow::anyhow!("Bad value {name}={value:?}: {e}"))
+}
+
+/// The directories that might hold a model, most preferred first.
+fn search_path(env: &dyn Fn(&str) -> Option<String>) -> Vec<PathBuf> {
+    let nonempty = |name| env(name).filter(|v: &String| !v.is_empty());
+    let mut dirs = Vec::new();
+    let data_home = nonempty("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| nonempty("HOME").map(|home| Path::new(&home).join(".local/share")));
+    dirs.extend(data_home.map(|d| d.join(DATA_DIR)));
+    let data_dirs =
+        nonempty("XDG_DATA_DIRS").unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+    dirs.extend(
+        data_dirs
+            .split(':')
+            .filter(|d| !d.is_empty())
+            .map(|d| Path::new(d).join(DATA_DIR)),
+    );
+    // For running from a checkout of this repository.
+    dirs.push(PathBuf::from("english"));
+    dirs
+}
+
+/// Pick the scorer in a model directory, if there is one.
+fn find_scorer(dir: &Path) -> anyhow::Result<Option<PathBuf>> {
+    let preferred = dir.join(PREFERRED_SCORER);
+    if preferred.is_file() {
+        return Ok(Some(preferred));
+    }
+    let mut scorers = Vec::new();
+    for entry in std::fs::read_dir(dir).with_context(|| format!("reading {dir:?}"))? {
+        let p = entry?.path();
+        if p.extension().map(|e| e == "scorer").unwrap_or(false) {
+            scorers.push(p);
+        }
+    }
+    scorers.sort();
+    Ok(scorers.into_iter().next())
+}
+
+#[cfg(test)]
+fn fake_env<'a>(vars: &'a [(&str, &str)]) -> impl 'a + Fn(&str) -> Option<String> {
+    move |name| {
+        vars.iter()
+            .find(|(k, _)| *k == name)
+            .map(|(_, v)| v.to_string())
+    }
+}
+
+#[test]
+fn xdg_search_path() {
+    let env = fake_env(&[
+        ("HOME", "/home/me"),
+        ("XDG_DATA_DIRS", "/opt/share::/usr/share"),
+    ]);
+    assert_eq!(
+        search_path(&env),
+        vec![
+            PathBuf::from("/home/me/.local/share/voice-control"),
+            PathBuf::from("/opt/share/voice-control"),
+            PathBuf::from("/usr/share/voice-control"),
+            PathBuf::from("english"),
+        ]
+    );
+    let env = fake_env(&[("HOME", "/home/me"), ("XDG_DATA_HOME", "/data")]);
+    assert_eq!(
+        search_path(&env)[..3],
+        [
+            PathBuf::from("/data/voice-control"),
+            PathBuf::from("/usr/local/share/voice-control"),
+            PathBuf::from("/usr/share/voice-control"),
+        ]
+    );
+}
+
+#[test]
+fn find_model_directory() {
+    let home = tempfile::tempdir().unwrap();
+    let data = home.path().join("data");
+    let dir = data.join("voice-control");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("model.tflite"), "").unwrap();
+    std::fs::write(dir.join("small.scorer"), "").unwrap();
+    std::fs::write(dir.join("big.scorer"), "").unwrap();
+
+    let data = data.to_str().unwrap();
+    let config = RecognizerConfig::find_with(&fake_env(&[("XDG_DATA_HOME", data)])).unwrap();
+    assert_eq!(config.model, dir.join("model.tflite"));
+    assert_eq!(config.scorer, Some(dir.join("big.scorer")));
+
+    std::fs::write(dir.join("huge-vocabulary.scorer"), "").unwrap();
+    let config = RecognizerConfig::find_with(&fake_env(&[
+        ("XDG_DATA_HOME", data),
+        ("VOICE_CONTROL_BEAM_WIDTH", "500"),
+        ("VOICE_CONTROL_PENALTY", "2.5"),
+    ]))
+    .unwrap();
+    assert_eq!(config.scorer, Some(dir.join("huge-vocabulary.scorer")));
+    assert_eq!(config.beam_width, Some(500));
+    assert_eq!(config.num_guesses, 16);
+    assert_eq!(config.penalty, 2.5);
+
+    let config = RecognizerConfig::find_with(&fake_env(&[
+        ("XDG_DATA_HOME", "/nowhere"),
+        (
+            "VOICE_CONTROL_MODEL",
+            dir.join("model.tflite").to_str().unwrap(),
+        ),
+    ]))
+    .unwrap();
+    assert_eq!(config, RecognizerConfig::new(dir.join("model.tflite")));
+}
+
+#[test]
+fn configuration_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let nowhere = dir.path().join("nowhere");
+    let nowhere = nowhere.to_str().unwrap();
+    let e =
+        RecognizerConfig::find_with(&fake_env(&[("VOICE_CONTROL_MODEL", nowhere)])).unwrap_err();
+    assert!(
+        format!("{e:#}").contains("There is no speech model"),
+        "{e:#}"
+    );
+
+    let e = RecognizerConfig::new("model.tflite")
+        .with_overrides(&fake_env(&[("VOICE_CONTROL_GUESSES", "lots")]))
+        .unwrap_err();
+    assert!(format!("{e}").contains("VOICE_CONTROL_GUESSES"), "{e}");
+}
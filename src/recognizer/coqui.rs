@@ -0,0 +1,126 @@
+//! Speech recognition with a [Coqui STT](https://github.com/coqui-ai/STT) model.
+
+use super::{Checker, RecognizerConfig, SpeechRecognizer, Transcript, UtteranceStream};
+use crate::parser::Error;
+use crate::REQUIRED_RATE;
+
+pub struct Coqui {
+    model: coqui_stt::Model,
+    num_guesses: u32,
+    penalty: f64,
+}
+
+impl Coqui {
+    pub fn load(config: &RecognizerConfig) -> anyhow::Result<Self> {
+        let path = &config.model;
+        let mut model = coqui_stt::Model::new(path.to_string_lossy())
+            .map_err(|e| anyhow::anyhow!("Unable to load speech model {path:?}: {e:?}"))?;
+        if let Some(scorer) = &config.scorer {
+            model
+                .enable_external_scorer(scorer.to_string_lossy())
+                .map_err(|e| anyhow::anyhow!("Unable to load scorer {scorer:?}: {e:?}"))?;
+        }
+        if let Some(width) = config.beam_width {
+            model
+                .set_model_beam_width(width)
+                .map_err(|e| anyhow::anyhow!("Unable to set beam width to {width}: {e:?}"))?;
+        }
+        if model.get_sample_rate() != REQUIRED_RATE.0 as i32 {
+            anyhow::bail!(
+                "Speech model {path:?} expects {} Hz audio rather than {} Hz",
+                model.get_sample_rate(),
+                REQUIRED_RATE.0
+            );
+        }
+        Ok(Coqui {
+            model,
+            num_guesses: config.num_guesses,
+            penalty: config.penalty,
+        })
+    }
+}
+
+impl SpeechRecognizer for Coqui {
+    fn set_checker(&mut self, checker: Checker) -> anyhow::Result<()> {
+        let penalty = self.penalty;
+        self.model
+            .enable_callback_scorer(move |s| {
+                let v = if let Err(Error::Wrong) = checker(s) {
+                    // println!("      bad input {:?}", s);
+                    -penalty
+                } else {
+                    // println!("      good input {:?}", s);
+                    0.0
+                };
+                // println!("score {v:4}: {s:?}");
+                v
+            })
+            .map_err(|e| anyhow::anyhow!("Unable to apply callback scorer: {e:?}"))
+    }
+
+    fn recognize(&mut self, audio: &[i16]) -> anyhow::Result<Vec<Transcript>> {
+        let metadata = self
+            .model
+            .speech_to_text_with_metadata(audio, self.num_guesses)
+            .map_err(|e| anyhow::anyhow!("Unable to recognize speech: {e:?}"))?;
+        Ok(transcripts(&metadata.to_owned()))
+    }
+
+    fn stream(&mut self) -> anyhow::Result<Box<dyn UtteranceStream + '_>> {
+        let num_guesses = self.num_guesses;
+        let stream = coqui_stt::Stream::from_model(&mut self.model)
+            .map_err(|e| anyhow::anyhow!("Unable to start recognizing speech: {e:?}"))?;
+        Ok(Box::new(CoquiStream {
+            stream,
+            num_guesses,
+        }))
+    }
+}
+
+struct CoquiStream<'a> {
+    stream: coqui_stt::Stream<'a>,
+    num_guesses: u32,
+}
+
+impl UtteranceStream for CoquiStream<'_> {
+    fn feed(&mut self, audio: &[i16]) {
+        self.stream.feed_audio(audio);
+    }
+
+    fn intermediate(&mut self) -> anyhow::Result<Option<String>> {
+        let text = self
+            .stream
+            .intermediate_decode()
+            .map_err(|e| anyhow::anyhow!("Unable to decode speech: {e:?}"))?;
+        Ok(Some(text))
+    }
+
+    fn finish(self: Box<Self>) -> anyhow::Result<Vec<Transcript>> {
+        let metadata = self
+            .stream
+            .finish_stream_with_metadata(self.num_guesses)
+            .map_err(|e| anyhow::anyhow!("Unable to recognize speech: {e:?}"))?;
+        Ok(transcripts(&metadata.to_owned()))
+    }
+}
+
+fn transcripts(metadata: &coqui_stt::OwnedMetadata) -> Vec<Transcript> {
+    metadata
+        .transcripts()
+        .iter()
+        .map(|c| {
+            let mut words = String::new();
+            for w in c.tokens().iter().map(|t| &t.text) {
+                words.push_str(w.as_ref());
+            }
+            // Remove trailing space.
+            while words.ends_with(' ') {
+                words.pop();
+            }
+            Transcript {
+                text: words,
+                confidence: c.confidence(),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,120 @@
+//! A pretend speech recognizer that hears whatever it is told to, so that
+//! everything after speech recognition can be tested without a model.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use super::{SpeechRecognizer, Transcript};
+
+/// Hears scripted transcripts in recognized pieces of audio.
+///
+/// Audio matches a script if, ignoring digital silence at either end, it
+/// hashes to the scripted hash, or is part of the scripted samples.  The
+/// latter means a WAV file still matches once voice activity detection has
+/// trimmed it.  Anything else is heard as nothing at all.
+#[derive(Default)]
+pub struct FakeRecognizer {
+    by_hash: HashMap<u64, Vec<Transcript>>,
+    by_samples: Vec<(Vec<i16>, Vec<Transcript>)>,
+}
+
+impl FakeRecognizer {
+    pub fn new() -> Self {
+        FakeRecognizer::default()
+    }
+
+    /// Hear `transcripts` in `samples`, or any part of them.
+    pub fn script(&mut self, samples: &[i16], transcripts: Vec<Transcript>) {
+        self.by_samples
+            .push((trim_silence(samples).to_vec(), transcripts));
+    }
+
+    /// Hear `transcripts` in a 16 kHz mono WAV file, or any part of it.
+    pub fn script_wav(
+        &mut self,
+        path: impl AsRef<Path>,
+        transcripts: Vec<Transcript>,
+    ) -> anyhow::Result<()> {
+        let samples = crate::audio::read_wav(path.as_ref())?;
+        self.script(&samples, transcripts);
+        Ok(())
+    }
+
+    /// Hear `transcripts` in audio with this [`hash_samples`].
+    pub fn script_hash(&mut self, hash: u64, transcripts: Vec<Transcript>) {
+        self.by_hash.insert(hash, transcripts);
+    }
+}
+
+/// A hash of audio, ignoring digital silence at either end.  It never
+/// changes, so it can be kept in scripts and test fixtures.
+pub fn hash_samples(samples: &[i16]) -> u64 {
+    crate::fnv1a(trim_silence(samples).iter().flat_map(|s| s.to_le_bytes()))
+}
+
+fn trim_silence(samples: &[i16]) -> &[i16] {
+    let start = samples
+        .iter()
+        .position(|&s| s != 0)
+        .unwrap_or(samples.len());
+    let end = samples
+        .iter()
+        .rposition(|&s| s != 0)
+        .map_or(start, |i| i + 1);
+    &samples[start..end]
+}
+
+impl SpeechRecognizer for FakeRecognizer {
+    fn recognize(&mut self, audio: &[i16]) -> anyhow::Result<Vec<Transcript>> {
+        let audio = trim_silence(audio);
+        if audio.is_empty() {
+            return Ok(Vec::new());
+        }
+        if let Some(transcripts) = self.by_hash.get(&hash_samples(audio)) {
+            return Ok(transcripts.clone());
+        }
+        Ok(self
+            .by_samples
+            .iter()
+            .find(|(samples, _)| samples.windows(audio.len()).any(|w| w == audio))
+            .map(|(_, transcripts)| transcripts.clone())
+            .unwrap_or_default())
+    }
+}
+
+#[test]
+fn scripted_transcripts() {
+    let mut fake = FakeRecognizer::new();
+    let hello: Vec<i16> = (1..1000).collect();
+    fake.script(&hello, vec![Transcript::new("hello", -1.0)]);
+    let goodbye = [5, 4, 3, 2, 1];
+    assert_eq!(0xc04e_ed66_d98a_6304, hash_samples(&goodbye));
+    fake.script_hash(
+        hash_samples(&goodbye),
+        vec![
+            Transcript::new("goodbye", -2.0),
+            Transcript::new("good buy", -3.0),
+        ],
+    );
+
+    let heard = |fake: &mut FakeRecognizer, audio: &[i16]| -> Vec<String> {
+        fake.recognize(audio)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.text)
+            .collect()
+    };
+    assert_eq!(heard(&mut fake, &hello), ["hello"]);
+    // Trimmed by the segmenter, with silence around it.
+    let mut trimmed = vec![0; 100];
+    trimmed.extend_from_slice(&hello[10..900]);
+    trimmed.extend_from_slice(&[0; 100]);
+    assert_eq!(heard(&mut fake, &trimmed), ["hello"]);
+
+    assert_eq!(
+        heard(&mut fake, &[0, 5, 4, 3, 2, 1, 0]),
+        ["goodbye", "good buy"]
+    );
+    assert!(heard(&mut fake, &[5, 4, 3, 2]).is_empty());
+    assert!(heard(&mut fake, &[0; 100]).is_empty());
+}
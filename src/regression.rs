@@ -0,0 +1,518 @@
+//! Checking how well we recognize a set of labelled clips, and whether a
+//! change to the grammar or the scorer made that better or worse.
+//!
+//! A manifest lists the clips, one JSON object per line, such as
+//! `{"wav": "one-up.wav", "text": "one up"}`.  Each clip gives either `text`,
+//! what was said, which should mean the same command as whatever we
+//! recognize; or `action`, the name of the action we should run; or
+//! neither, for clips that shouldn't trigger anything.  Text that isn't a
+//! command fails, in case the grammar lost a command, unless the clip also
+//! says `"not_a_command": true`.  Relative paths are relative to the
+//! manifest.  Blank lines and lines starting with `#` are ignored.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::archive::Clip;
+use crate::desktop_control::Action;
+use crate::parser::{IsParser, Parser};
+use crate::recognition::Recognition;
+
+/// What we call it when no action is expected, or none was recognized.
+pub const NOTHING: &str = "(nothing)";
+
+/// What we expect when a case's text should be a command, but isn't, so
+/// the case can't pass.
+pub const NOT_A_COMMAND: &str = "(not a command)";
+
+/// A labelled clip.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Case {
+    pub wav: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action: Option<String>,
+    /// The text isn't meant to be a command, so we expect nothing.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub not_a_command: bool,
+}
+
+impl Case {
+    /// The name of the action we expect, or [`NOT_A_COMMAND`] if the text
+    /// should be a command but isn't.
+    fn expected(&self, commands: &impl IsParser<Output = Action>) -> String {
+        if let Some(action) = &self.action {
+            return action.clone();
+        }
+        match &self.text {
+            Some(text) if !self.not_a_command => match commands.parse(text) {
+                Ok((action, "")) => action.name().to_string(),
+                _ => NOT_A_COMMAND.to_string(),
+            },
+            _ => NOTHING.to_string(),
+        }
+    }
+}
+
+/// Read the cases in a manifest.
+pub fn read_manifest(path: &Path) -> anyhow::Result<Vec<Case>> {
+    let manifest = std::fs::read_to_string(path).with_context(|| format!("reading {path:?}"))?;
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut cases = Vec::new();
+    for (i, line) in manifest.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut case: Case = serde_json::from_str(line)
+            .with_context(|| format!("reading line {} of {path:?}", i + 1))?;
+        case.wav = directory.join(&case.wav);
+        cases.push(case);
+    }
+    Ok(cases)
+}
+
+/// The archived clips somebody has [corrected](crate::review), as cases.
+pub fn cases_from_clips(clips: &[Clip]) -> anyhow::Result<Vec<Case>> {
+    let mut cases = Vec::new();
+    for clip in clips {
+        let metadata = clip.metadata()?;
+        if let Some(correction) = metadata.correction {
+            cases.push(Case {
+                wav: clip.wav.clone(),
+                text: Some(correction).filter(|c| !c.is_empty()),
+                action: None,
+                not_a_command: metadata.not_a_command,
+            });
+        }
+    }
+    Ok(cases)
+}
+
+/// How we did on one case.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CaseResult {
+    pub wav: PathBuf,
+    pub expected: String,
+    pub got: String,
+    /// What was said, if we know.
+    #[serde(default)]
+    pub reference: Option<String>,
+    /// The recognizer's best guess at what was said.
+    pub transcript: Option<String>,
+    pub passed: bool,
+}
+
+impl CaseResult {
+    /// How we did on `case`, given that we made `recognition` of it.
+    pub fn new(
+        case: &Case,
+        recognition: &Recognition<Action>,
+        commands: &impl IsParser<Output = Action>,
+    ) -> Self {
+        let expected = case.expected(commands);
+        let got = match &recognition.action {
+            Some(action) => action.name().to_string(),
+            None => NOTHING.to_string(),
+        };
+        CaseResult {
+            wav: case.wav.clone(),
+            passed: expected == got && expected != NOT_A_COMMAND,
+            expected,
+            got,
+            reference: case.text.clone(),
+            transcript: recognition.hypotheses.first().map(|h| h.text.clone()),
+        }
+    }
+}
+
+/// How we did on every case.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Report {
+    pub results: Vec<CaseResult>,
+}
+
+/// What changed since a baseline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Changes<'a> {
+    /// Cases that passed in the baseline and fail now.
+    pub regressions: Vec<&'a CaseResult>,
+    /// Cases that failed in the baseline and pass now.
+    pub fixes: Vec<&'a CaseResult>,
+}
+
+/// Run every case through `recognize`, which should be steered towards
+/// `commands`, such as a function from
+/// [`load_voice_control`](crate::load_voice_control).
+pub fn run_regression(
+    cases: &[Case],
+    commands: &Parser<Action>,
+    mut recognize: impl FnMut(&[i16]) -> anyhow::Result<Recognition<Action>>,
+) -> anyhow::Result<Report> {
+    // Read the text the same way the recognized phrase is read.
+    let commands = commands.compile();
+    let mut results = Vec::with_capacity(cases.len());
+    for case in cases {
+        let samples = crate::audio::read_wav(&case.wav)?;
+        let recognition =
+            recognize(&samples).with_context(|| format!("recognizing {:?}", case.wav))?;
+        results.push(CaseResult::new(case, &recognition, &commands));
+    }
+    Ok(Report { results })
+}
+
+impl Report {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let json = std::fs::read_to_string(path).with_context(|| format!("reading {path:?}"))?;
+        serde_json::from_str(&json).with_context(|| format!("parsing {path:?}"))
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json).with_context(|| format!("saving {path:?}"))
+    }
+
+    pub fn passed(&self) -> usize {
+        self.results.iter().filter(|r| r.passed).count()
+    }
+
+    /// The fraction of cases that passed.
+    pub fn accuracy(&self) -> f64 {
+        if self.results.is_empty() {
+            return 0.0;
+        }
+        self.passed() as f64 / self.results.len() as f64
+    }
+
+    /// Word errors in the best transcripts per word of the cases we know the
+    /// text of.
+    pub fn word_error_rate(&self) -> Option<f64> {
+        let mut errors = 0;
+        let mut words = 0;
+        for r in &self.results {
+            if let Some(reference) = &r.reference {
+                errors += word_errors(reference, r.transcript.as_deref().unwrap_or(""));
+                words += reference.split_whitespace().count();
+            }
+        }
+        (words > 0).then(|| errors as f64 / words as f64)
+    }
+
+    /// The fraction of cases with a command that we got right.
+    pub fn command_accuracy(&self) -> Option<f64> {
+        rate(self.results.iter().filter(|r| r.expected != NOTHING), |r| {
+            r.passed
+        })
+    }
+
+    /// The fraction of cases without a command that triggered one anyway.
+    pub fn false_trigger_rate(&self) -> Option<f64> {
+        rate(self.results.iter().filter(|r| r.expected == NOTHING), |r| {
+            !r.passed
+        })
+    }
+
+    /// The word error rate, command accuracy and false trigger rate, on
+    /// one line.
+    pub fn metrics(&self) -> String {
+        let percent = |x: Option<f64>| match x {
+            Some(x) => format!("{:.1}%", x * 100.0),
+            None => "n/a".to_string(),
+        };
+        format!(
+            "WER {}, command accuracy {}, false triggers {}",
+            percent(self.word_error_rate()),
+            percent(self.command_accuracy()),
+            percent(self.false_trigger_rate())
+        )
+    }
+
+    /// For each expected action, how often we got each action.
+    pub fn confusion(&self) -> BTreeMap<&str, BTreeMap<&str, usize>> {
+        let mut confusion: BTreeMap<&str, BTreeMap<&str, usize>> = BTreeMap::new();
+        for r in &self.results {
+            *confusion
+                .entry(&r.expected)
+                .or_default()
+                .entry(&r.got)
+                .or_default() += 1;
+        }
+        confusion
+    }
+
+    /// Which cases got better or worse since `baseline`.  Cases that aren't
+    /// in the baseline are ignored.
+    pub fn changes_since(&self, baseline: &Report) -> Changes<'_> {
+        let before: BTreeMap<&Path, bool> = baseline
+            .results
+            .iter()
+            .map(|r| (r.wav.as_path(), r.passed))
+            .collect();
+        let mut changes = Changes {
+            regressions: Vec::new(),
+            fixes: Vec::new(),
+        };
+        for r in &self.results {
+            match before.get(r.wav.as_path()) {
+                Some(true) if !r.passed => changes.regressions.push(r),
+                Some(false) if r.passed => changes.fixes.push(r),
+                _ => (),
+            }
+        }
+        changes
+    }
+
+    /// Describe the results, and what changed since `baseline`.
+    pub fn describe(&self, baseline: Option<&Report>) -> String {
+        let mut out = String::new();
+        writeln!(
+            out,
+            "Passed {} of {} ({:.1}%)",
+            self.passed(),
+            self.results.len(),
+            self.accuracy() * 100.0
+        )
+        .ok();
+        writeln!(out, "{}", self.metrics()).ok();
+        if let Some(baseline) = baseline {
+            writeln!(
+                out,
+                "The baseline passed {} of {} ({:.1}%)",
+                baseline.passed(),
+                baseline.results.len(),
+                baseline.accuracy() * 100.0
+            )
+            .ok();
+        }
+        for (expected, got) in self.confusion() {
+            let total: usize = got.values().sum();
+            let right = got.get(expected).copied().unwrap_or(0);
+            write!(out, "  {expected}: {right} of {total}").ok();
+            for (got, n) in got.iter().filter(|(got, _)| **got != expected) {
+                write!(out, ", {n} as {got}").ok();
+            }
+            writeln!(out).ok();
+        }
+        let describe_case = |out: &mut String, r: &CaseResult| {
+            writeln!(
+                out,
+                "  {}: expected {}, got {} from {:?}",
+                r.wav.display(),
+                r.expected,
+                r.got,
+                r.transcript.as_deref().unwrap_or("")
+            )
+            .ok();
+        };
+        if let Some(baseline) = baseline {
+            let changes = self.changes_since(baseline);
+            if !changes.regressions.is_empty() {
+                writeln!(out, "Regressions:").ok();
+                for r in changes.regressions {
+                    describe_case(&mut out, r);
+                }
+            }
+            if !changes.fixes.is_empty() {
+                writeln!(out, "Fixed:").ok();
+                for r in changes.fixes {
+                    describe_case(&mut out, r);
+                }
+            }
+        } else {
+            let failures: Vec<_> = self.results.iter().filter(|r| !r.passed).collect();
+            if !failures.is_empty() {
+                writeln!(out, "Failures:").ok();
+                for r in failures {
+                    describe_case(&mut out, r);
+                }
+            }
+        }
+        out
+    }
+}
+
+fn rate<'a>(
+    results: impl Iterator<Item = &'a CaseResult>,
+    f: impl Fn(&CaseResult) -> bool,
+) -> Option<f64> {
+    let (mut yes, mut total) = (0, 0);
+    for r in results {
+        total += 1;
+        if f(r) {
+            yes += 1;
+        }
+    }
+    (total > 0).then(|| yes as f64 / total as f64)
+}
+
+/// How many words must be inserted, deleted or replaced to turn `reference`
+/// into `hypothesis`.
+pub fn word_errors(reference: &str, hypothesis: &str) -> usize {
+    let hypothesis: Vec<&str> = hypothesis.split_whitespace().collect();
+    // The distance from the reference so far to each prefix of the hypothesis.
+    let mut distances: Vec<usize> = (0..=hypothesis.len()).collect();
+    for (i, r) in reference.split_whitespace().enumerate() {
+        let mut previous = distances[0];
+        distances[0] = i + 1;
+        for (j, h) in hypothesis.iter().enumerate() {
+            let substitution = previous + usize::from(r != *h);
+            previous = distances[j + 1];
+            distances[j + 1] = substitution.min(distances[j] + 1).min(distances[j + 1] + 1);
+        }
+    }
+    distances[hypothesis.len()]
+}
+
+#[test]
+fn count_word_errors() {
+    assert_eq!(word_errors("testing one two", "testing one two"), 0);
+    assert_eq!(word_errors("testing one two", "testing two"), 1);
+    assert_eq!(word_errors("testing two", "testing one two"), 1);
+    assert_eq!(word_errors("one up", "won up please"), 2);
+    assert_eq!(word_errors("one up", ""), 2);
+    assert_eq!(word_errors("", "hello there"), 2);
+}
+
+#[test]
+fn read_a_manifest() {
+    let dir = tempfile::tempdir().unwrap();
+    let manifest = dir.path().join("manifest.jsonl");
+    std::fs::write(
+        &manifest,
+        r#"# Clips we should recognize
+{"wav": "one-up.wav", "text": "one up"}
+
+{"wav": "/clips/testing.wav", "action": "Testing!"}
+{"wav": "noise.wav"}
+{"wav": "chatter.wav", "text": "what's for lunch", "not_a_command": true}
+"#,
+    )
+    .unwrap();
+    let cases = read_manifest(&manifest).unwrap();
+    assert_eq!(
+        cases,
+        [
+            Case {
+                wav: dir.path().join("one-up.wav"),
+                text: Some("one up".to_string()),
+                action: None,
+                not_a_command: false,
+            },
+            Case {
+                wav: "/clips/testing.wav".into(),
+                text: None,
+                action: Some("Testing!".to_string()),
+                not_a_command: false,
+            },
+            Case {
+                wav: dir.path().join("noise.wav"),
+                text: None,
+                action: None,
+                not_a_command: false,
+            },
+            Case {
+                wav: dir.path().join("chatter.wav"),
+                text: Some("what's for lunch".to_string()),
+                action: None,
+                not_a_command: true,
+            },
+        ]
+    );
+
+    std::fs::write(&manifest, "{\"text\": \"one up\"}\n").unwrap();
+    let e = read_manifest(&manifest).unwrap_err();
+    assert!(format!("{e:#}").contains("line 1"), "{e:#}");
+}
+
+#[test]
+fn regression_report() {
+    use crate::parser::IntoParser;
+    use crate::recognizer::{FakeRecognizer, Transcript};
+
+    let commands = || "testing".many1().map(|t| Action::new(t.join(" "), || ()));
+    let mut fake = FakeRecognizer::new();
+    let mut script = |file: &str, text: &str| {
+        let wav = format!("test-audio/{file}.wav");
+        fake.script_wav(wav, vec![Transcript::new(text, -1.0)])
+            .unwrap();
+    };
+    script("testing", "testing");
+    script("testing-testing-testing", "testing testing");
+    script("one-up", "resting");
+    script("testing-testing-testing-unrecognized", "testing one two");
+    let case = |file: &str, text: Option<&str>, action: Option<&str>| Case {
+        wav: format!("test-audio/{file}.wav").into(),
+        text: text.map(String::from),
+        action: action.map(String::from),
+        not_a_command: false,
+    };
+    let cases = [
+        case("testing", Some("testing"), None),
+        case(
+            "testing-testing-testing",
+            None,
+            Some("testing testing testing"),
+        ),
+        // Chatter we shouldn't act on.
+        Case {
+            not_a_command: true,
+            ..case("one-up", Some("one up"), None)
+        },
+        // A command the grammar doesn't have (any more), so it can't pass.
+        case(
+            "testing-testing-testing-unrecognized",
+            Some("testing one two"),
+            None,
+        ),
+    ];
+    let recognize =
+        crate::load_voice_control(fake, &crate::choice::ChoiceConfig::default(), commands).unwrap();
+    let report = run_regression(&cases, &commands(), recognize).unwrap();
+    assert_eq!(report.passed(), 2);
+    // "one up" was heard as "resting".
+    assert_eq!(report.word_error_rate(), Some(2.0 / 6.0));
+    assert_eq!(report.command_accuracy(), Some(1.0 / 3.0));
+    assert_eq!(report.false_trigger_rate(), Some(0.0));
+    let e = expect_test::expect![[r#"
+        Passed 2 of 4 (50.0%)
+        WER 33.3%, command accuracy 33.3%, false triggers 0.0%
+          (not a command): 0 of 1, 1 as (nothing)
+          (nothing): 1 of 1
+          testing: 1 of 1
+          testing testing testing: 0 of 1, 1 as testing testing
+        Failures:
+          test-audio/testing-testing-testing.wav: expected testing testing testing, got testing testing from "testing testing"
+          test-audio/testing-testing-testing-unrecognized.wav: expected (not a command), got (nothing) from "testing one two"
+    "#]];
+    e.assert_eq(&report.describe(None));
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("baseline.json");
+    let mut baseline = report.clone();
+    baseline.results[0].passed = false;
+    baseline.results[1].passed = true;
+    baseline.save(&path).unwrap();
+    let baseline = Report::load(&path).unwrap();
+    let changes = report.changes_since(&baseline);
+    assert_eq!(changes.regressions, [&report.results[1]]);
+    assert_eq!(changes.fixes, [&report.results[0]]);
+    let e = expect_test::expect![[r#"
+        Passed 2 of 4 (50.0%)
+        WER 33.3%, command accuracy 33.3%, false triggers 0.0%
+        The baseline passed 2 of 4 (50.0%)
+          (not a command): 0 of 1, 1 as (nothing)
+          (nothing): 1 of 1
+          testing: 1 of 1
+          testing testing testing: 0 of 1, 1 as testing testing
+        Regressions:
+          test-audio/testing-testing-testing.wav: expected testing testing testing, got testing testing from "testing testing"
+        Fixed:
+          test-audio/testing.wav: expected testing, got testing from "testing"
+    "#]];
+    e.assert_eq(&report.describe(Some(&baseline)));
+}
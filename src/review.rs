@@ -0,0 +1,256 @@
+//! Listening back to archived clips and saying what was really said, so that
+//! they can be used for training and regression tests.
+
+use std::io::{BufRead, Write};
+
+use crate::archive::{Clip, ClipMetadata};
+use crate::parser::ParseError;
+
+/// What to say at the start of a review.
+pub const INSTRUCTIONS: &str = "\
+For each clip, type what was said, or the number of the guess that got it right.
+A line starting with ! is kept even if it isn't a command, and - means there was
+no command at all.  Press r to hear the clip again, enter to skip it and q to stop.";
+
+/// How a review went.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReviewSummary {
+    pub corrected: usize,
+    pub skipped: usize,
+}
+
+/// What the reviewer told us about a clip.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Answer {
+    /// This is what was said, and it had better be a command unless
+    /// `force` is set.
+    Text {
+        text: String,
+        force: bool,
+    },
+    /// The guess with this number was right.
+    Pick(usize),
+    Replay,
+    Skip,
+    Quit,
+}
+
+fn parse_answer(line: &str) -> Answer {
+    let line = line.trim();
+    match line {
+        "" => Answer::Skip,
+        "q" => Answer::Quit,
+        "r" => Answer::Replay,
+        "-" => Answer::Text {
+            text: String::new(),
+            force: true,
+        },
+        _ => {
+            if let Ok(n) = line.parse() {
+                return Answer::Pick(n);
+            }
+            let (line, force) = match line.strip_prefix('!') {
+                Some(line) => (line, true),
+                None => (line, false),
+            };
+            let words: Vec<String> = line.split_whitespace().map(|w| w.to_lowercase()).collect();
+            Answer::Text {
+                text: words.join(" "),
+                force,
+            }
+        }
+    }
+}
+
+/// Whether a clip still wants reviewing: nobody has corrected it, and
+/// unless we are reviewing `all` of them, we didn't recognize it either.
+pub fn needs_review(metadata: &ClipMetadata, all: bool) -> bool {
+    metadata.correction.is_none() && (all || metadata.chosen.is_none())
+}
+
+/// Go through the `clips` that [need review](needs_review), playing each
+/// one and asking `input` what was said.  Corrections must pass `check`
+/// unless the reviewer insists, and are saved in the clip's metadata.
+pub fn review_clips(
+    clips: &[Clip],
+    all: bool,
+    check: impl Fn(&str) -> Result<(), ParseError>,
+    mut play: impl FnMut(&[i16]) -> anyhow::Result<()>,
+    mut input: impl BufRead,
+    mut output: impl Write,
+) -> anyhow::Result<ReviewSummary> {
+    let mut summary = ReviewSummary::default();
+    for clip in clips {
+        let mut metadata = clip.metadata()?;
+        if !needs_review(&metadata, all) {
+            continue;
+        }
+        let samples = clip.samples()?;
+        writeln!(
+            output,
+            "\n{} ({:.1} seconds)",
+            clip.wav.display(),
+            metadata.end - metadata.start
+        )?;
+        for (i, h) in metadata.hypotheses.iter().enumerate() {
+            let mark = if Some(i) == metadata.chosen {
+                "=>"
+            } else {
+                "  "
+            };
+            writeln!(
+                output,
+                "{mark} {}. {:.2}: {:?} {:?}",
+                i + 1,
+                h.confidence,
+                h.text,
+                h.outcome
+            )?;
+        }
+        let mut replay = true;
+        let correction = loop {
+            if replay {
+                if let Err(e) = play(&samples) {
+                    writeln!(output, "Couldn't play it: {e:#}")?;
+                }
+                replay = false;
+            }
+            write!(output, "> ")?;
+            output.flush()?;
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(summary);
+            }
+            let (text, force) = match parse_answer(&line) {
+                Answer::Quit => return Ok(summary),
+                Answer::Skip => break None,
+                Answer::Replay => {
+                    replay = true;
+                    continue;
+                }
+                Answer::Pick(n) => {
+                    match n.checked_sub(1).and_then(|i| metadata.hypotheses.get(i)) {
+                        Some(h) => (h.text.clone(), false),
+                        None => {
+                            writeln!(output, "There is no guess number {n}")?;
+                            continue;
+                        }
+                    }
+                }
+                Answer::Text { text, force } => (text, force),
+            };
+            match check(&text) {
+                Err(e) if !force => writeln!(
+                    output,
+                    "{text:?} isn't a command: {e}.  Start it with ! to keep it anyway."
+                )?,
+                result => break Some((text, result.is_err())),
+            }
+        };
+        match correction {
+            Some((correction, not_a_command)) => {
+                metadata.correction = Some(correction);
+                metadata.not_a_command = not_a_command;
+                clip.save_metadata(&metadata)?;
+                summary.corrected += 1;
+            }
+            None => summary.skipped += 1,
+        }
+    }
+    Ok(summary)
+}
+
+#[test]
+fn parse_answers() {
+    assert_eq!(parse_answer("\n"), Answer::Skip);
+    assert_eq!(parse_answer(" q\n"), Answer::Quit);
+    assert_eq!(parse_answer("r"), Answer::Replay);
+    assert_eq!(parse_answer("2"), Answer::Pick(2));
+    assert_eq!(
+        parse_answer("Go  to Sleep\n"),
+        Answer::Text {
+            text: "go to sleep".to_string(),
+            force: false
+        }
+    );
+    assert_eq!(
+        parse_answer("!hello there"),
+        Answer::Text {
+            text: "hello there".to_string(),
+            force: true
+        }
+    );
+    assert_eq!(
+        parse_answer("-"),
+        Answer::Text {
+            text: String::new(),
+            force: true
+        }
+    );
+}
+
+#[test]
+fn review_an_archive() {
+    use crate::archive::{metadata, Archive, ArchiveConfig};
+    use crate::parser::IntoParser;
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut archive = Archive::open(&ArchiveConfig::new(dir.path())).unwrap();
+    let mut save = |texts: &[&str], chosen: Option<usize>| {
+        let metadata = metadata(texts, chosen, None);
+        archive.save(&[1, 2, 3], "clip", &metadata).unwrap().0
+    };
+    let recognized = save(&["testing"], Some(0));
+    let first = save(&["resting", "testing testing"], None);
+    let second = save(&["vesting"], None);
+    let third = save(&["jesting"], None);
+
+    let check = "testing".many1().to_diagnoser();
+    let mut plays = 0;
+    let input = "r\nresting\n3\n2\n\n!Jest Ing\n";
+    let mut output = Vec::new();
+    let clips = archive.clips().unwrap();
+    let play = |samples: &[i16]| {
+        assert_eq!(samples, [1, 2, 3]);
+        plays += 1;
+        Ok(())
+    };
+    let summary = review_clips(&clips, false, check, play, input.as_bytes(), &mut output).unwrap();
+    assert_eq!(
+        summary,
+        ReviewSummary {
+            corrected: 2,
+            skipped: 1
+        }
+    );
+    assert_eq!(plays, 4);
+    let correction = |clip: &Clip| clip.metadata().unwrap().correction;
+    assert_eq!(correction(&recognized), None);
+    assert_eq!(correction(&first), Some("testing testing".to_string()));
+    assert_eq!(correction(&second), None);
+    assert_eq!(correction(&third), Some("jest ing".to_string()));
+    let not_a_command = |clip: &Clip| clip.metadata().unwrap().not_a_command;
+    assert!(!not_a_command(&first));
+    assert!(not_a_command(&third));
+    let output = String::from_utf8(output).unwrap();
+    assert!(
+        output.contains("\"resting\" isn't a command: expected testing."),
+        "{output}"
+    );
+    assert!(output.contains("There is no guess number 3"), "{output}");
+
+    // Corrected clips aren't reviewed again, but recognized ones can be.
+    let check = "testing".many1().to_diagnoser();
+    let summary = review_clips(
+        &clips,
+        true,
+        check,
+        |_| Ok(()),
+        "-\nq\n".as_bytes(),
+        Vec::new(),
+    )
+    .unwrap();
+    assert_eq!(summary.corrected, 1);
+    assert_eq!(correction(&recognized), Some(String::new()));
+    assert_eq!(correction(&second), None);
+}
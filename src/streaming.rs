@@ -0,0 +1,248 @@
+//! Recognizing speech while it is still being spoken, so that once the
+//! speaker stops there is little decoding left to do.
+
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+
+use anyhow::Context;
+
+use crate::events::Events;
+use crate::parser::Error;
+use crate::recognizer::{Checker, SpeechRecognizer, Transcript};
+
+/// How much audio we feed the model between intermediate decodes.
+const PARTIAL_INTERVAL: usize = crate::RATE_AS_USIZE / 2;
+
+enum Request {
+    Audio(Vec<i16>),
+    Finish,
+    Cancel,
+}
+
+enum Update {
+    Partial { utterance: usize, text: String },
+    Final(Vec<Transcript>),
+}
+
+/// Feeds utterances to a [`SpeechRecognizer`] as they are spoken.
+///
+/// The recognizer lives on a thread of its own, which decodes audio as soon
+/// as it arrives, so finishing an utterance only has to wait for the last
+/// little bit.
+pub struct StreamingRecognizer {
+    requests: Sender<Request>,
+    updates: Receiver<Update>,
+    /// How many utterances we have finished or cancelled.
+    utterance: usize,
+    /// How much of the current utterance the model has been given.
+    fed: usize,
+}
+
+impl StreamingRecognizer {
+    /// Create a recognizer with `load` on a thread of its own, steering it
+    /// towards phrases that pass `checker`.  Errors along the way are sent
+    /// to `events`.
+    pub fn new<R: SpeechRecognizer>(
+        load: impl 'static + Send + FnOnce() -> anyhow::Result<R>,
+        checker: impl 'static + Send + Sync + Fn(&str) -> Result<(), Error>,
+        events: &Events,
+    ) -> anyhow::Result<Self> {
+        let events = events.clone();
+        let (requests, incoming) = channel();
+        let (outgoing, updates) = channel();
+        let (ready, is_ready) = channel();
+        std::thread::spawn(move || {
+            let checker: Checker = Arc::new(checker);
+            let loaded = load().and_then(|mut recognizer| {
+                recognizer.set_checker(checker.clone())?;
+                Ok(recognizer)
+            });
+            match loaded {
+                Ok(mut recognizer) => {
+                    ready.send(Ok(())).ok();
+                    recognize_utterances(&mut recognizer, incoming, outgoing, &events);
+                }
+                Err(e) => {
+                    ready.send(Err(e)).ok();
+                }
+            }
+        });
+        is_ready
+            .recv()
+            .context("the speech recognition thread died")??;
+        Ok(StreamingRecognizer {
+            requests,
+            updates,
+            utterance: 0,
+            fed: 0,
+        })
+    }
+
+    /// Catch up with the utterance in progress, given all of it so far.
+    pub fn update(&mut self, so_far: &[i16]) {
+        if so_far.len() > self.fed {
+            self.requests
+                .send(Request::Audio(so_far[self.fed..].to_vec()))
+                .ok();
+            self.fed = so_far.len();
+        }
+    }
+
+    /// What the model has made of the current utterance so far, oldest
+    /// first, since we last asked.
+    pub fn partial_results(&mut self) -> Vec<String> {
+        let current = self.utterance;
+        self.updates
+            .try_iter()
+            .filter_map(|update| match update {
+                Update::Partial { utterance, text } if utterance == current => Some(text),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Finish up the utterance, given the whole of it, returning the
+    /// model's guesses at what was said.
+    pub fn finish(&mut self, utterance: &[i16]) -> Vec<Transcript> {
+        self.update(utterance);
+        self.fed = 0;
+        self.utterance += 1;
+        if self.requests.send(Request::Finish).is_err() {
+            return Vec::new();
+        }
+        while let Ok(update) = self.updates.recv() {
+            if let Update::Final(transcripts) = update {
+                return transcripts;
+            }
+        }
+        Vec::new()
+    }
+
+    /// Forget the utterance in progress.
+    pub fn cancel(&mut self) {
+        self.requests.send(Request::Cancel).ok();
+        self.fed = 0;
+        self.utterance += 1;
+    }
+}
+
+/// Decode each utterance as it arrives, until the [`StreamingRecognizer`]
+/// goes away.
+fn recognize_utterances(
+    recognizer: &mut dyn SpeechRecognizer,
+    requests: Receiver<Request>,
+    updates: Sender<Update>,
+    events: &Events,
+) {
+    let mut utterance = 0;
+    loop {
+        let first = match requests.recv() {
+            Ok(Request::Audio(samples)) => samples,
+            Ok(Request::Finish) => {
+                // We never heard anything.
+                updates.send(Update::Final(Vec::new())).ok();
+                utterance += 1;
+                continue;
+            }
+            Ok(Request::Cancel) => {
+                utterance += 1;
+                continue;
+            }
+            Err(_) => return,
+        };
+        // Each utterance gets a stream of its own, which has the recognizer
+        // to itself until it is finished.
+        let mut stream = match recognizer.stream() {
+            Ok(stream) => Some(stream),
+            Err(e) => {
+                events.error(&e);
+                None
+            }
+        };
+        if let Some(stream) = &mut stream {
+            stream.feed(&first);
+        }
+        let mut since_partial = first.len();
+        let mut last_partial = String::new();
+        loop {
+            match requests.recv() {
+                Ok(Request::Audio(samples)) => {
+                    let stream = match &mut stream {
+                        Some(stream) => stream,
+                        None => continue,
+                    };
+                    stream.feed(&samples);
+                    since_partial += samples.len();
+                    if since_partial >= PARTIAL_INTERVAL {
+                        since_partial = 0;
+                        if let Ok(Some(text)) = stream.intermediate() {
+                            if text != last_partial {
+                                last_partial = text.clone();
+                                updates.send(Update::Partial { utterance, text }).ok();
+                            }
+                        }
+                    }
+                }
+                Ok(Request::Finish) => {
+                    let transcripts = match stream.map(|stream| stream.finish()) {
+                        Some(Ok(transcripts)) => transcripts,
+                        Some(Err(e)) => {
+                            events.error(&e);
+                            Vec::new()
+                        }
+                        None => Vec::new(),
+                    };
+                    updates.send(Update::Final(transcripts)).ok();
+                    break;
+                }
+                Ok(Request::Cancel) => break,
+                Err(_) => return,
+            }
+        }
+        utterance += 1;
+    }
+}
+
+#[test]
+fn stream_testing() {
+    use crate::parser::IntoParser;
+    use crate::recognizer::FakeRecognizer;
+
+    let load = || {
+        let mut fake = FakeRecognizer::new();
+        fake.script_wav(
+            "test-audio/testing.wav",
+            vec![Transcript::new("testing", -1.0)],
+        )?;
+        fake.script_wav(
+            "test-audio/testing-testing-testing.wav",
+            vec![
+                Transcript::new("testing testing testing", -1.0),
+                Transcript::new("testing testing", -2.0),
+            ],
+        )?;
+        Ok(fake)
+    };
+    let parser = "testing".many1();
+    let mut recognizer =
+        StreamingRecognizer::new(load, parser.to_checker(), &Events::new()).unwrap();
+    for name in ["testing", "testing-testing-testing"] {
+        let sound = crate::load_data(&format!("test-audio/{name}.wav"));
+        for end in (1024..sound.len()).step_by(1024) {
+            recognizer.update(&sound[..end]);
+        }
+        let result = recognizer.finish(&sound);
+        println!("Result is {result:?}");
+        assert_eq!(result[0].text, name.replace('-', " "));
+    }
+    // A cancelled utterance doesn't affect the next one.
+    let sound = crate::load_data("test-audio/testing.wav");
+    recognizer.update(&sound[..5000]);
+    recognizer.cancel();
+    assert_eq!(
+        vec![Transcript::new("testing", -1.0)],
+        recognizer.finish(&sound)
+    );
+    // Nothing in, nothing out.
+    assert!(recognizer.finish(&[]).is_empty());
+}
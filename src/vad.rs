@@ -0,0 +1,559 @@
+//! Voice activity detection, which decides where phrases begin and end.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::RATE_AS_USIZE;
+
+/// How eager the WebRTC voice activity detector is to call something silence.
+///
+/// A more aggressive mode copes better with background noise, but is more
+/// likely to clip quiet speech.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VadMode {
+    Quality,
+    LowBitrate,
+    Aggressive,
+    VeryAggressive,
+}
+
+impl std::str::FromStr for VadMode {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "quality" => Ok(VadMode::Quality),
+            "low-bitrate" => Ok(VadMode::LowBitrate),
+            "aggressive" => Ok(VadMode::Aggressive),
+            "very-aggressive" => Ok(VadMode::VeryAggressive),
+            _ => Err(anyhow::anyhow!(
+                "VAD mode must be quality, low-bitrate, aggressive or very-aggressive, not {s:?}"
+            )),
+        }
+    }
+}
+
+/// The length of audio the detector looks at in one go.  WebRTC only
+/// supports these three.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameLength {
+    Ms10,
+    Ms20,
+    Ms30,
+}
+
+impl FrameLength {
+    pub fn samples(self) -> usize {
+        match self {
+            FrameLength::Ms10 => RATE_AS_USIZE / 100,
+            FrameLength::Ms20 => RATE_AS_USIZE / 50,
+            FrameLength::Ms30 => 3 * RATE_AS_USIZE / 100,
+        }
+    }
+}
+
+impl std::str::FromStr for FrameLength {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "10" => Ok(FrameLength::Ms10),
+            "20" => Ok(FrameLength::Ms20),
+            "30" => Ok(FrameLength::Ms30),
+            _ => Err(anyhow::anyhow!(
+                "VAD frames must be 10, 20 or 30 ms long, not {s:?}"
+            )),
+        }
+    }
+}
+
+/// How we split the audio stream into phrases.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VadConfig {
+    pub mode: VadMode,
+    pub frame: FrameLength,
+    /// How long a pause ends a phrase.
+    pub silence: Duration,
+    /// Phrases with less speech than this are ignored as clicks and coughs.
+    pub min_utterance: Duration,
+    /// How much audio from before the speech started to keep, so we don't
+    /// clip the first consonant.
+    pub pre_roll: Duration,
+    /// Utterances longer than this are thrown away, so that a radio playing
+    /// in the background can't fill up our memory.
+    pub max_utterance: Duration,
+}
+
+impl Default for VadConfig {
+    fn default() -> Self {
+        VadConfig {
+            mode: VadMode::VeryAggressive,
+            frame: FrameLength::Ms30,
+            silence: Duration::from_millis(250),
+            min_utterance: Duration::ZERO,
+            pre_roll: Duration::from_millis(250),
+            max_utterance: Duration::from_secs(15),
+        }
+    }
+}
+
+pub(crate) fn duration_to_samples(d: Duration) -> usize {
+    (d.as_secs_f64() * RATE_AS_USIZE as f64).round() as usize
+}
+
+/// Decides whether a single frame of audio contains speech.
+pub trait VoiceDetector {
+    fn is_voice(&mut self, frame: &[i16]) -> bool;
+}
+
+impl<F: FnMut(&[i16]) -> bool> VoiceDetector for F {
+    fn is_voice(&mut self, frame: &[i16]) -> bool {
+        self(frame)
+    }
+}
+
+/// The WebRTC voice activity detector.
+pub struct WebRtcVad(webrtc_vad::Vad);
+
+impl WebRtcVad {
+    pub fn new(mode: VadMode) -> Self {
+        let mode = match mode {
+            VadMode::Quality => webrtc_vad::VadMode::Quality,
+            VadMode::LowBitrate => webrtc_vad::VadMode::LowBitrate,
+            VadMode::Aggressive => webrtc_vad::VadMode::Aggressive,
+            VadMode::VeryAggressive => webrtc_vad::VadMode::VeryAggressive,
+        };
+        WebRtcVad(webrtc_vad::Vad::new_with_rate_and_mode(
+            webrtc_vad::SampleRate::Rate16kHz,
+            mode,
+        ))
+    }
+}
+
+impl VoiceDetector for WebRtcVad {
+    fn is_voice(&mut self, frame: &[i16]) -> bool {
+        self.0
+            .is_voice_segment(frame)
+            .expect("wrong size data sample")
+    }
+}
+
+/// A stretch of speech found by a [`PhraseSegmenter`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Utterance {
+    /// When the utterance (including its pre-roll) began, measured from the
+    /// start of the audio stream.
+    pub start: Duration,
+    /// When it ended, including the silence that ended it.
+    pub end: Duration,
+    pub samples: Vec<i16>,
+}
+
+/// What a [`PhraseSegmenter`] noticed in the audio.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SegmentEvent {
+    /// Someone started talking.
+    Started { start: Duration },
+    /// They finished.
+    Ended(Utterance),
+    /// The utterance went on longer than `max_utterance`, so we threw it away
+    /// and are waiting for silence before listening again, or it had less
+    /// speech than `min_utterance`, so we threw it away at the end.
+    Abandoned { start: Duration, end: Duration },
+}
+
+/// Splits a stream of audio into utterances.
+pub trait Segmenter {
+    /// Feed in some audio, getting back whatever happened in it.
+    fn push(&mut self, samples: &[i16]) -> Vec<SegmentEvent>;
+    /// Finish up any utterance in progress, as if it were followed by silence.
+    fn flush(&mut self) -> Option<SegmentEvent>;
+    /// Forget any utterance in progress, e.g. because the audio it came from
+    /// was interrupted.
+    fn reset(&mut self);
+    /// Let `samples` go by without listening to them, forgetting any
+    /// utterance in progress, but keeping track of the time.
+    fn skip(&mut self, samples: usize);
+    /// The audio of the utterance in progress so far, if there is one.
+    fn current(&self) -> Option<&[i16]>;
+}
+
+/// Splits a stream of audio into utterances separated by silence.
+pub struct PhraseSegmenter<D> {
+    detector: D,
+    frame_samples: usize,
+    silence_samples: usize,
+    min_speech_samples: usize,
+    max_samples: usize,
+    pre_roll_samples: usize,
+    /// Samples that don't yet fill a frame.
+    partial: Vec<i16>,
+    /// How many samples we have examined so far.
+    position: usize,
+    /// Recent silence, in case speech starts.
+    pre_roll: VecDeque<i16>,
+    state: State,
+}
+
+enum State {
+    Idle,
+    Speaking(Phrase),
+    /// Waiting for the end of an utterance that was too long.
+    Ignoring {
+        trailing_silence: usize,
+    },
+}
+
+struct Phrase {
+    start: usize,
+    samples: Vec<i16>,
+    speech_samples: usize,
+    trailing_silence: usize,
+}
+
+pub(crate) fn samples_to_duration(samples: usize) -> Duration {
+    Duration::from_nanos(samples as u64 * 1_000_000_000 / RATE_AS_USIZE as u64)
+}
+
+impl PhraseSegmenter<WebRtcVad> {
+    pub fn new(config: &VadConfig) -> Self {
+        PhraseSegmenter::with_detector(config, WebRtcVad::new(config.mode))
+    }
+}
+
+impl<D: VoiceDetector> PhraseSegmenter<D> {
+    pub fn with_detector(config: &VadConfig, detector: D) -> Self {
+        PhraseSegmenter {
+            detector,
+            frame_samples: config.frame.samples(),
+            silence_samples: duration_to_samples(config.silence),
+            min_speech_samples: duration_to_samples(config.min_utterance),
+            max_samples: duration_to_samples(config.max_utterance),
+            pre_roll_samples: duration_to_samples(config.pre_roll),
+            partial: Vec::new(),
+            position: 0,
+            pre_roll: VecDeque::new(),
+            state: State::Idle,
+        }
+    }
+
+    fn finish_phrase(&mut self) -> Option<SegmentEvent> {
+        match std::mem::replace(&mut self.state, State::Idle) {
+            State::Speaking(phrase) => {
+                let start = samples_to_duration(phrase.start);
+                let end = samples_to_duration(phrase.start + phrase.samples.len());
+                Some(if phrase.speech_samples >= self.min_speech_samples {
+                    SegmentEvent::Ended(Utterance {
+                        start,
+                        end,
+                        samples: phrase.samples,
+                    })
+                } else {
+                    SegmentEvent::Abandoned { start, end }
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn push_frame(&mut self, frame: &[i16]) -> Option<SegmentEvent> {
+        let is_voice = self.detector.is_voice(frame);
+        match &mut self.state {
+            State::Speaking(phrase) => {
+                // Include the silence, which might include the very end of a consonant.
+                phrase.samples.extend_from_slice(frame);
+                if is_voice {
+                    phrase.speech_samples += phrase.trailing_silence + frame.len();
+                    phrase.trailing_silence = 0;
+                } else {
+                    phrase.trailing_silence += frame.len();
+                    if phrase.trailing_silence >= self.silence_samples {
+                        return self.finish_phrase();
+                    }
+                }
+                if phrase.samples.len() >= self.max_samples {
+                    let start = samples_to_duration(phrase.start);
+                    let end = samples_to_duration(phrase.start + phrase.samples.len());
+                    self.state = State::Ignoring {
+                        trailing_silence: phrase.trailing_silence,
+                    };
+                    return Some(SegmentEvent::Abandoned { start, end });
+                }
+            }
+            State::Ignoring { trailing_silence } => {
+                if is_voice {
+                    *trailing_silence = 0;
+                } else {
+                    *trailing_silence += frame.len();
+                    if *trailing_silence >= self.silence_samples {
+                        self.state = State::Idle;
+                    }
+                }
+            }
+            State::Idle if is_voice => {
+                let mut samples: Vec<i16> = self.pre_roll.drain(..).collect();
+                let start = self.position - samples.len();
+                samples.extend_from_slice(frame);
+                self.state = State::Speaking(Phrase {
+                    start,
+                    samples,
+                    speech_samples: frame.len(),
+                    trailing_silence: 0,
+                });
+                return Some(SegmentEvent::Started {
+                    start: samples_to_duration(start),
+                });
+            }
+            State::Idle => {
+                self.pre_roll.extend(frame);
+                while self.pre_roll.len() > self.pre_roll_samples {
+                    self.pre_roll.pop_front();
+                }
+            }
+        }
+        None
+    }
+}
+
+impl<D: VoiceDetector> Segmenter for PhraseSegmenter<D> {
+    fn push(&mut self, samples: &[i16]) -> Vec<SegmentEvent> {
+        let mut events = Vec::new();
+        self.partial.extend_from_slice(samples);
+        let whole_frames = self.partial.len() - self.partial.len() % self.frame_samples;
+        let frames: Vec<i16> = self.partial.drain(..whole_frames).collect();
+        for frame in frames.chunks_exact(self.frame_samples) {
+            events.extend(self.push_frame(frame));
+            self.position += frame.len();
+        }
+        events
+    }
+
+    fn flush(&mut self) -> Option<SegmentEvent> {
+        // There may be more to come, if we are only pausing.
+        self.position += self.partial.len();
+        self.partial.clear();
+        self.pre_roll.clear();
+        self.finish_phrase()
+    }
+
+    fn reset(&mut self) {
+        self.position += self.partial.len();
+        self.partial.clear();
+        self.pre_roll.clear();
+        self.state = State::Idle;
+    }
+
+    fn skip(&mut self, samples: usize) {
+        self.position += samples;
+        self.reset();
+    }
+
+    fn current(&self) -> Option<&[i16]> {
+        match &self.state {
+            State::Speaking(phrase) => Some(&phrase.samples),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+fn tone(ms: usize) -> Vec<i16> {
+    (0..ms * RATE_AS_USIZE / 1000)
+        .map(|i| (8000.0 * (i as f64 * 0.2).sin()) as i16)
+        .collect()
+}
+
+#[cfg(test)]
+fn silence(ms: usize) -> Vec<i16> {
+    vec![0; ms * RATE_AS_USIZE / 1000]
+}
+
+#[cfg(test)]
+fn energy_detector(frame: &[i16]) -> bool {
+    frame.iter().map(|&s| (s as i64).abs()).sum::<i64>() > 1000 * frame.len() as i64
+}
+
+#[cfg(test)]
+fn segment_events(config: &VadConfig, audio: &[Vec<i16>]) -> Vec<SegmentEvent> {
+    let mut segmenter = PhraseSegmenter::with_detector(config, energy_detector);
+    let mut events = Vec::new();
+    for chunk in audio {
+        // Feed in awkwardly sized pieces, which shouldn't matter.
+        for piece in chunk.chunks(1234) {
+            events.extend(segmenter.push(piece));
+        }
+    }
+    events.extend(segmenter.flush());
+    events
+}
+
+#[cfg(test)]
+fn segment(config: &VadConfig, audio: &[Vec<i16>]) -> Vec<Vec<i16>> {
+    segment_events(config, audio)
+        .into_iter()
+        .filter_map(|e| match e {
+            SegmentEvent::Ended(u) => Some(u.samples),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn segment_tones() {
+    let config = VadConfig {
+        pre_roll: Duration::ZERO,
+        ..VadConfig::default()
+    };
+    let phrases = segment(
+        &config,
+        &[
+            silence(600),
+            tone(300),
+            silence(600),
+            tone(600),
+            silence(100),
+        ],
+    );
+    let lengths: Vec<usize> = phrases.iter().map(|p| p.len()).collect();
+    // The first phrase includes the silence that ended it.  The second is
+    // flushed at the end.
+    assert_eq!(lengths, vec![16 * (300 + 270), 16 * (600 + 90)]);
+    assert!(phrases[0][..16 * 300].iter().any(|&s| s != 0));
+    assert!(phrases[0][16 * 300..].iter().all(|&s| s == 0));
+}
+
+#[test]
+fn short_pauses_do_not_split_phrases() {
+    let audio = [tone(300), silence(200), tone(300), silence(300)];
+    assert_eq!(1, segment(&VadConfig::default(), &audio).len());
+
+    let snappy = VadConfig {
+        silence: Duration::from_millis(150),
+        ..VadConfig::default()
+    };
+    assert_eq!(2, segment(&snappy, &audio).len());
+}
+
+#[test]
+fn pre_roll_keeps_audio_before_speech() {
+    let mut lead_in = silence(500);
+    // A quiet consonant the detector doesn't notice.
+    lead_in[16 * 400..].iter_mut().for_each(|s| *s = 5);
+    let audio = [lead_in, tone(300), silence(300)];
+
+    let config = VadConfig {
+        pre_roll: Duration::from_millis(150),
+        frame: FrameLength::Ms10,
+        ..VadConfig::default()
+    };
+    let phrases = segment(&config, &audio);
+    assert_eq!(1, phrases.len());
+    assert_eq!(phrases[0].len(), 16 * (150 + 300 + 250));
+    assert_eq!(phrases[0][16 * 50 - 1], 0);
+    assert_eq!(phrases[0][16 * 50], 5);
+}
+
+#[test]
+fn minimum_utterance_length() {
+    let audio = [
+        silence(300),
+        tone(60),
+        silence(300),
+        tone(400),
+        silence(300),
+    ];
+    assert_eq!(2, segment(&VadConfig::default(), &audio).len());
+
+    let config = VadConfig {
+        min_utterance: Duration::from_millis(200),
+        ..VadConfig::default()
+    };
+    let phrases = segment(&config, &audio);
+    assert_eq!(1, phrases.len());
+    assert!(phrases[0].len() > 16 * 400);
+    // The click still started an utterance, so we say we threw it away.
+    let events = segment_events(&config, &audio);
+    assert!(matches!(events[1], SegmentEvent::Abandoned { .. }));
+}
+
+#[test]
+fn parse_config_options() {
+    assert_eq!(Ok(VadMode::Quality), "quality".parse().map_err(|_| ()));
+    assert!("loud".parse::<VadMode>().is_err());
+    assert_eq!(160, "10".parse::<FrameLength>().unwrap().samples());
+    assert_eq!(480, "30".parse::<FrameLength>().unwrap().samples());
+    assert!("25".parse::<FrameLength>().is_err());
+}
+
+#[test]
+fn utterance_timestamps() {
+    let config = VadConfig {
+        pre_roll: Duration::from_millis(90),
+        ..VadConfig::default()
+    };
+    let ms = Duration::from_millis;
+    let events = segment_events(&config, &[silence(600), tone(300), silence(600)]);
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0], SegmentEvent::Started { start: ms(510) });
+    if let SegmentEvent::Ended(u) = &events[1] {
+        assert_eq!(u.start, ms(510));
+        assert_eq!(u.end, ms(600 + 300 + 270));
+        assert_eq!(u.samples.len(), 16 * (90 + 300 + 270));
+    } else {
+        panic!("expected the end of an utterance, not {:?}", events[1]);
+    }
+}
+
+#[test]
+fn overly_long_utterances_are_abandoned() {
+    let config = VadConfig {
+        pre_roll: Duration::ZERO,
+        max_utterance: Duration::from_millis(900),
+        ..VadConfig::default()
+    };
+    let ms = Duration::from_millis;
+    // A radio that pauses too briefly to end the utterance, then a command.
+    let radio = [
+        tone(600),
+        silence(150),
+        tone(600),
+        silence(150),
+        tone(600),
+        silence(300),
+    ];
+    let mut audio = radio.to_vec();
+    audio.push(tone(300));
+    audio.push(silence(300));
+    let events = segment_events(&config, &audio);
+    assert_eq!(
+        events[..2],
+        [
+            SegmentEvent::Started { start: ms(0) },
+            SegmentEvent::Abandoned {
+                start: ms(0),
+                end: ms(900)
+            },
+        ]
+    );
+    // We ignore the rest of the radio, but hear the command.
+    assert_eq!(events.len(), 4);
+    assert_eq!(events[2], SegmentEvent::Started { start: ms(2400) });
+    assert!(matches!(&events[3], SegmentEvent::Ended(u) if u.samples.len() == 16 * 570));
+}
+
+#[test]
+fn reset_forgets_the_utterance_in_progress() {
+    let mut segmenter = PhraseSegmenter::with_detector(&VadConfig::default(), energy_detector);
+    // Not a whole number of frames, so some of it is still waiting.
+    assert_eq!(1, segmenter.push(&tone(310)).len());
+    segmenter.reset();
+    assert_eq!(Vec::<SegmentEvent>::new(), segmenter.push(&silence(600)));
+    assert_eq!(None, segmenter.flush());
+
+    // The time still counts, though.
+    let events = segmenter.push(&tone(300));
+    assert_eq!(
+        events,
+        [SegmentEvent::Started {
+            start: Duration::from_millis(910)
+        }]
+    );
+}